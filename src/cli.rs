@@ -0,0 +1,318 @@
+use std::fs::OpenOptions;
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::convert::{
+    convert_batch_auto, inspect, scan_files, BatchSummary, Collision, ConvertFormat,
+    ConvertOptions, Converter, DecodeLimits, ProgressEvent, QualityThreshold,
+};
+
+const USAGE: &str = "Usage: image_convert_gui [OPTIONS] FILES...
+
+Converts every file next to itself. Without arguments the GUI starts when
+built with the gui feature.
+
+Options:
+  -f, --format FORMAT  jpeg, png, webp, bmp, gif, avif or auto (default jpeg),
+                       repeat to write several formats per file
+  -q, --quality N      Encoder quality 1-100 for JPEG, WebP and AVIF
+      --max-dimension N
+                       Refuse images wider or taller than N pixels
+      --memory-budget MB
+                       Memory a batch may use for decoded images at once
+      --timeout SECS   Give up on a file after SECS seconds and discard its
+                       outputs
+      --overwrite      Replace existing outputs instead of numbering them
+      --skip-existing  Leave out outputs that already exist, skipping inputs
+                       with nothing left to write
+      --verify         Measure PSNR and SSIM of every output
+      --fix-extension  Rename inputs whose extension doesn't match their content
+      --report FILE    Write a per-file report, .csv or .json
+      --log FILE       Append progress events as JSON lines while converting
+      --info           Print format, dimensions and metadata as JSON instead
+                       of converting
+      --scan           Decode every file without writing output and list
+                       corrupt, truncated or mislabeled ones
+  -h, --help           Show this help";
+
+struct CliArgs {
+    formats: Vec<ConvertFormat>,
+    auto: bool,
+    options: ConvertOptions,
+    overwrite: bool,
+    skip_existing: bool,
+    report: Option<PathBuf>,
+    log: Option<PathBuf>,
+    info: bool,
+    scan: bool,
+    files: Vec<PathBuf>,
+}
+
+// Returns the process exit code: 0 when everything converted, 1 when some
+// files failed and 2 for bad arguments
+pub fn run(args: &[String]) -> i32 {
+    let mut args = match parse_args(args) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return 2;
+        }
+    };
+
+    if args.info {
+        return print_info(&args.files, &args.options.limits);
+    }
+    if args.scan {
+        return print_scan(&args.files, &args.options.limits);
+    }
+
+    let log = match &args.log {
+        Some(path) => match start_log(path, &mut args.options) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                eprintln!("Could not open log {}: {}", path.display(), e);
+                return 1;
+            }
+        },
+        None => None,
+    };
+
+    let summary = if args.auto {
+        convert_batch_auto(args.files, &args.options, args.overwrite, |_, _| {})
+    } else {
+        let collision = if args.overwrite {
+            Collision::Overwrite
+        } else if args.skip_existing {
+            Collision::Skip
+        } else {
+            Collision::Number
+        };
+        let converter = args
+            .formats
+            .iter()
+            .fold(
+                Converter::builder().options(args.options),
+                |builder, format| builder.format(format.clone()),
+            )
+            .collision(collision)
+            .build();
+        match converter {
+            Ok(converter) => converter.convert_many(args.files, |_, _| {}),
+            Err(e) => {
+                eprintln!("{}", e);
+                return 2;
+            }
+        }
+    };
+
+    if let Some(log) = log {
+        let _ = log.join();
+    }
+    print_summary(&summary);
+
+    if let Some(report) = &args.report {
+        match summary.write_report(report) {
+            Ok(()) => println!("Report written to {}", report.display()),
+            Err(e) => {
+                eprintln!("Could not write report: {}", e);
+                return 1;
+            }
+        }
+    }
+
+    if summary.error_count == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Option<CliArgs>, String> {
+    let mut parsed = CliArgs {
+        formats: Vec::new(),
+        auto: false,
+        options: ConvertOptions::default(),
+        overwrite: false,
+        skip_existing: false,
+        report: None,
+        log: None,
+        info: false,
+        scan: false,
+        files: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-f" | "--format" => {
+                let name = value(arg)?;
+                if name.eq_ignore_ascii_case("auto") {
+                    parsed.auto = true;
+                } else {
+                    let format = ConvertFormat::from_name(&name)
+                        .ok_or_else(|| format!("Unknown format: {}", name))?;
+                    if !parsed.formats.contains(&format) {
+                        parsed.formats.push(format);
+                    }
+                }
+            }
+            "-q" | "--quality" => {
+                let quality = value(arg)?;
+                let quality: u8 = quality
+                    .parse()
+                    .ok()
+                    .filter(|q| (1..=100).contains(q))
+                    .ok_or_else(|| format!("Quality must be 1-100, got {}", quality))?;
+                parsed.options.encoder.quality = Some(quality);
+            }
+            "--max-dimension" => {
+                let pixels = value(arg)?;
+                let pixels: u32 = pixels
+                    .parse()
+                    .ok()
+                    .filter(|&p| p > 0)
+                    .ok_or_else(|| format!("Invalid dimension: {}", pixels))?;
+                parsed.options.limits.max_width = pixels;
+                parsed.options.limits.max_height = pixels;
+            }
+            "--memory-budget" => {
+                let megabytes = value(arg)?;
+                let megabytes: u64 = megabytes
+                    .parse()
+                    .ok()
+                    .filter(|&mb| mb > 0)
+                    .ok_or_else(|| format!("Invalid memory budget: {}", megabytes))?;
+                parsed.options.limits.memory_budget = megabytes * 1024 * 1024;
+            }
+            "--timeout" => {
+                let seconds = value(arg)?;
+                let seconds: f64 = seconds
+                    .parse()
+                    .ok()
+                    .filter(|&s: &f64| s > 0.0 && s.is_finite())
+                    .ok_or_else(|| format!("Invalid timeout: {}", seconds))?;
+                parsed.options.timeout = Some(Duration::from_secs_f64(seconds));
+            }
+            "--overwrite" => parsed.overwrite = true,
+            "--skip-existing" => parsed.skip_existing = true,
+            "--verify" => parsed.options.verify = Some(QualityThreshold::default()),
+            "--fix-extension" => parsed.options.fix_extension = true,
+            "--report" => parsed.report = Some(PathBuf::from(value(arg)?)),
+            "--log" => parsed.log = Some(PathBuf::from(value(arg)?)),
+            "--info" => parsed.info = true,
+            "--scan" => parsed.scan = true,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ => parsed.files.push(PathBuf::from(arg)),
+        }
+    }
+
+    if parsed.files.is_empty() {
+        return Err("No input files given".to_string());
+    }
+    if parsed.auto && !parsed.formats.is_empty() {
+        return Err("auto cannot be combined with other formats".to_string());
+    }
+    if parsed.skip_existing && (parsed.overwrite || parsed.auto) {
+        return Err("--skip-existing cannot be combined with --overwrite or auto".to_string());
+    }
+    if parsed.formats.is_empty() {
+        parsed.formats.push(ConvertFormat::Jpeg);
+    }
+
+    Ok(Some(parsed))
+}
+
+// Writes events on a thread of its own until the batch is done, so the log
+// keeps up with the conversion
+fn start_log(path: &Path, options: &mut ConvertOptions) -> Result<JoinHandle<()>, Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let (sender, receiver) = mpsc::channel();
+    options.events = Some(sender);
+    Ok(std::thread::spawn(move || {
+        for event in receiver {
+            if let Err(e) = writeln!(file, "{}", event.to_json()) {
+                eprintln!("Could not write log: {}", e);
+                return;
+            }
+            if matches!(event, ProgressEvent::BatchDone { .. }) {
+                return;
+            }
+        }
+    }))
+}
+
+// Prints a JSON array with one object per readable file
+fn print_info(files: &[PathBuf], limits: &DecodeLimits) -> i32 {
+    let mut objects = Vec::new();
+    let mut failed = false;
+    for file in files {
+        match inspect(file, limits) {
+            Ok(info) => objects.push(info.to_json().trim_end().to_string()),
+            Err(e) => {
+                eprintln!("{}: {}", file.display(), e);
+                failed = true;
+            }
+        }
+    }
+    println!("[\n{}\n]", objects.join(",\n"));
+    i32::from(failed)
+}
+
+// Exits with 1 when any file has a problem, so it can gate a release
+fn print_scan(files: &[PathBuf], limits: &DecodeLimits) -> i32 {
+    let results = scan_files(files, limits, |_, _| {});
+    let problems = results.iter().filter(|result| !result.is_ok()).count();
+    for result in &results {
+        println!("{}: {}", result.path.display(), result.describe());
+    }
+    println!(
+        "{} files verified, {} with problems",
+        results.len(),
+        problems
+    );
+    i32::from(problems > 0)
+}
+
+fn print_summary(summary: &BatchSummary) {
+    for record in &summary.records {
+        let input = record.input.display();
+        match (&record.output, &record.stats, &record.error) {
+            (Some(output), Some(stats), _) => println!(
+                "{} -> {} ({}x{}, {} bytes)",
+                input,
+                output.display(),
+                stats.width,
+                stats.height,
+                stats.output_bytes
+            ),
+            (_, _, Some(error)) => eprintln!("{}: {}", input, error),
+            _ => {}
+        }
+        if let Some(mismatch) = &record.extension_mismatch {
+            eprintln!("{}: {}", input, mismatch.describe());
+        }
+    }
+    for skipped in &summary.skipped {
+        println!("{}: skipped, every output exists", skipped.display());
+    }
+    println!(
+        "{} converted, {} failed, {} skipped",
+        summary.success_count,
+        summary.error_count,
+        summary.skipped.len()
+    );
+}
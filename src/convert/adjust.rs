@@ -0,0 +1,137 @@
+use image::{ColorType, DynamicImage, Rgba32FImage};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adjustments {
+    // -100..=100, shifts every channel by a percentage of full scale
+    pub brightness: f32,
+    // -100..=100, same scale as `DynamicImage::adjust_contrast`
+    pub contrast: f32,
+    // 1.0 leaves the image unchanged, larger values brighten midtones
+    pub gamma: f32,
+    // Degrees around the color wheel
+    pub hue_rotation: f32,
+    // 0.0 removes all color, 1.0 leaves the image unchanged
+    pub saturation: f32,
+    pub grayscale: bool,
+    pub invert: bool,
+}
+
+impl Default for Adjustments {
+    fn default() -> Self {
+        Adjustments {
+            brightness: 0.0,
+            contrast: 0.0,
+            gamma: 1.0,
+            hue_rotation: 0.0,
+            saturation: 1.0,
+            grayscale: false,
+            invert: false,
+        }
+    }
+}
+
+impl Adjustments {
+    pub fn is_identity(&self) -> bool {
+        *self == Adjustments::default()
+    }
+}
+
+// Adjustments run in a fixed order: brightness, contrast, gamma, saturation,
+// hue, grayscale and finally invert
+pub fn apply_adjustments(img: DynamicImage, adjustments: &Adjustments) -> DynamicImage {
+    if adjustments.is_identity() {
+        return img;
+    }
+
+    let color = img.color();
+    let mut buffer = img.into_rgba32f();
+
+    let brightness = adjustments.brightness.clamp(-100.0, 100.0) / 100.0;
+    let contrast = ((100.0 + adjustments.contrast.clamp(-100.0, 100.0)) / 100.0).powi(2);
+    let inverse_gamma = 1.0 / adjustments.gamma.max(0.01);
+    let hue_matrix = hue_rotation_matrix(adjustments.hue_rotation);
+
+    for pixel in buffer.pixels_mut() {
+        let mut rgb = [pixel[0], pixel[1], pixel[2]];
+
+        for c in rgb.iter_mut() {
+            *c = (*c + brightness).clamp(0.0, 1.0);
+            *c = ((*c - 0.5) * contrast + 0.5).clamp(0.0, 1.0);
+            *c = c.powf(inverse_gamma);
+        }
+
+        if adjustments.saturation != 1.0 {
+            let l = luma(rgb);
+            for c in rgb.iter_mut() {
+                *c = (l + (*c - l) * adjustments.saturation.max(0.0)).clamp(0.0, 1.0);
+            }
+        }
+
+        if adjustments.hue_rotation != 0.0 {
+            let [r, g, b] = rgb;
+            for (c, row) in rgb.iter_mut().zip(hue_matrix) {
+                *c = (row[0] * r + row[1] * g + row[2] * b).clamp(0.0, 1.0);
+            }
+        }
+
+        if adjustments.grayscale {
+            rgb = [luma(rgb); 3];
+        }
+
+        if adjustments.invert {
+            rgb = rgb.map(|c| 1.0 - c);
+        }
+
+        pixel[0] = rgb[0];
+        pixel[1] = rgb[1];
+        pixel[2] = rgb[2];
+    }
+
+    restore_color_type(buffer, color)
+}
+
+fn luma(rgb: [f32; 3]) -> f32 {
+    0.2126 * rgb[0] + 0.7152 * rgb[1] + 0.0722 * rgb[2]
+}
+
+// Same rotation matrix `imageops::huerotate` uses
+fn hue_rotation_matrix(degrees: f32) -> [[f32; 3]; 3] {
+    let angle = degrees.to_radians();
+    let cosv = angle.cos();
+    let sinv = angle.sin();
+
+    [
+        [
+            0.213 + cosv * 0.787 - sinv * 0.213,
+            0.715 - cosv * 0.715 - sinv * 0.715,
+            0.072 - cosv * 0.072 + sinv * 0.928,
+        ],
+        [
+            0.213 - cosv * 0.213 + sinv * 0.143,
+            0.715 + cosv * 0.285 + sinv * 0.140,
+            0.072 - cosv * 0.072 - sinv * 0.283,
+        ],
+        [
+            0.213 - cosv * 0.213 - sinv * 0.787,
+            0.715 - cosv * 0.715 + sinv * 0.715,
+            0.072 + cosv * 0.928 + sinv * 0.072,
+        ],
+    ]
+}
+
+// Hand the image back in the color type it was decoded with
+pub(super) fn restore_color_type(buffer: Rgba32FImage, color: ColorType) -> DynamicImage {
+    let img = DynamicImage::ImageRgba32F(buffer);
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(img.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(img.to_rgb8()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(img.to_rgba8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(img.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(img.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(img.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(img.to_rgb32f()),
+        _ => img,
+    }
+}
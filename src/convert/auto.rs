@@ -0,0 +1,216 @@
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::encode::{self, EncoderSettings};
+use super::{
+    check_color_type, check_extension, load_image, measure, output_path_for, run_batch,
+    write_output, BatchSummary, ClaimedNames, ConvertFormat, ConvertOptions, ConvertStats,
+    ConvertedFile, OutputTarget, QualityMetrics,
+};
+
+#[derive(Debug, Clone)]
+pub struct AutoFormat {
+    // Every candidate is encoded, the smallest one above the floor is kept
+    pub candidates: Vec<OutputTarget>,
+    // Lowest SSIM against the source a candidate may have, 1.0 means lossless
+    pub min_ssim: f64,
+}
+
+impl Default for AutoFormat {
+    fn default() -> Self {
+        let candidate = |format, quality| OutputTarget {
+            format,
+            encoder: EncoderSettings {
+                quality: Some(quality),
+                ..EncoderSettings::default()
+            },
+        };
+
+        AutoFormat {
+            candidates: vec![
+                candidate(ConvertFormat::Webp, 80),
+                candidate(ConvertFormat::Jpeg, 85),
+            ],
+            min_ssim: 0.95,
+        }
+    }
+}
+
+// Writes the winning candidate next to the input. Its format and SSIM end up
+// in the returned stats.
+pub fn convert_image_auto(
+    input_path: PathBuf,
+    options: &ConvertOptions,
+    overwrite: bool,
+) -> Result<(PathBuf, ConvertStats), Error> {
+    let output_path = |format: &ConvertFormat| output_path_for(&input_path, format, overwrite);
+    convert_auto(&input_path, options, output_path)
+}
+
+// `output_path` names the file once the winning format is known
+fn convert_auto(
+    input_path: &Path,
+    options: &ConvertOptions,
+    output_path: impl FnOnce(&ConvertFormat) -> PathBuf,
+) -> Result<(PathBuf, ConvertStats), Error> {
+    let auto = &options.auto;
+    check_candidates(auto)?;
+
+    let img = load_image(input_path, options)?;
+
+    let mut best: Option<(&OutputTarget, encode::Encoded, QualityMetrics)> = None;
+    // Best score seen below the floor, to explain a failure
+    let mut best_rejected: Option<(&ConvertFormat, f64)> = None;
+    for target in &auto.candidates {
+        let encoded = match check_color_type(&target.format, options)
+            .and_then(|_| encode::encode_image(&img, target, options))
+        {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                eprintln!("Skipping {} candidate: {}", target.format.name(), e);
+                continue;
+            }
+        };
+
+        // Candidates were checked to be decodable, so this only fails on a
+        // broken encode, which is reported like a failed one
+        let Some(metrics) = measure(&img, &target.format, &encoded.bytes) else {
+            continue;
+        };
+        let score = metrics.ssim;
+
+        if score < auto.min_ssim {
+            if best_rejected.is_none_or(|(_, best_score)| score > best_score) {
+                best_rejected = Some((&target.format, score));
+            }
+            continue;
+        }
+
+        if best
+            .as_ref()
+            .is_none_or(|(_, smallest, _)| encoded.bytes.len() < smallest.bytes.len())
+        {
+            best = Some((target, encoded, metrics));
+        }
+    }
+
+    let Some((target, encoded, metrics)) = best else {
+        return Err(Error::other(match best_rejected {
+            Some((format, score)) => format!(
+                "No candidate reached SSIM {:.3} (best was {} at {:.3})",
+                auto.min_ssim,
+                format.name(),
+                score
+            ),
+            None => "No candidate format could be encoded and scored".to_string(),
+        }));
+    };
+
+    let output_path = output_path(&target.format);
+    if let Err(e) = write_output(&output_path, &encoded.bytes) {
+        eprintln!("Failed to save image: {}", e);
+        return Err(Error::other(format!("Save failed: {}", e)));
+    }
+    println!(
+        "Successfully converted {} to {} ({} won)",
+        input_path.display(),
+        output_path.display(),
+        target.format.name()
+    );
+
+    Ok((
+        output_path,
+        ConvertStats {
+            format: target.format.clone(),
+            width: encoded.width,
+            height: encoded.height,
+            output_bytes: encoded.bytes.len() as u64,
+            quality: encoded.quality,
+            metrics: Some(metrics),
+        },
+    ))
+}
+
+// Every candidate is decoded again to be scored, a format this build can only
+// encode could never win
+fn check_candidates(auto: &AutoFormat) -> Result<(), Error> {
+    if auto.candidates.is_empty() {
+        return Err(Error::other("No candidate formats configured"));
+    }
+    match auto.candidates.iter().find(|t| !t.format.can_decode()) {
+        Some(target) => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "{} cannot be an Auto candidate, it can't be decoded to be scored",
+                target.format.name()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
+
+pub fn convert_batch_auto(
+    files: Vec<PathBuf>,
+    options: &ConvertOptions,
+    overwrite: bool,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+) -> BatchSummary {
+    let options_clone = options.clone();
+    let claimed = ClaimedNames::default();
+    run_batch(files, options, progress_callback, move |file_path| {
+        let output_path =
+            |format: &ConvertFormat| claimed.claim_next_to(file_path, format, overwrite);
+        Ok(ConvertedFile {
+            input: file_path.clone(),
+            outputs: vec![convert_auto(file_path, &options_clone, output_path)?],
+            rule: None,
+            extension_mismatch: check_extension(file_path, &options_clone),
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::*;
+
+    #[test]
+    fn undecodable_candidates_are_refused() {
+        let dir = temp_dir("auto_avif");
+        let input = write_png(&dir, "photo.png", 8, 8);
+        let options = ConvertOptions {
+            auto: AutoFormat {
+                candidates: vec![
+                    OutputTarget::new(ConvertFormat::Webp),
+                    OutputTarget::new(ConvertFormat::Avif),
+                ],
+                min_ssim: 0.5,
+            },
+            ..ConvertOptions::default()
+        };
+        let error = convert_image_auto(input, &options, false).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(error.to_string().contains("AVIF"));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn keeps_the_smallest_candidate_above_the_floor() {
+        let dir = temp_dir("auto_pick");
+        let input = write_png(&dir, "photo.png", 32, 32);
+        let options = ConvertOptions {
+            auto: AutoFormat {
+                candidates: vec![
+                    OutputTarget::new(ConvertFormat::Bmp),
+                    OutputTarget::new(ConvertFormat::Png),
+                ],
+                min_ssim: 0.99,
+            },
+            ..ConvertOptions::default()
+        };
+        let (path, stats) = convert_image_auto(input, &options, false).unwrap();
+        assert_eq!(stats.format, ConvertFormat::Png);
+        assert!(stats.metrics.unwrap().ssim > 0.99);
+        assert!(path.exists());
+    }
+}
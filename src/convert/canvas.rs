@@ -0,0 +1,86 @@
+use image::{imageops, ColorType, DynamicImage, Rgba, Rgba32FImage};
+
+use super::adjust::restore_color_type;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+}
+
+impl Alignment {
+    fn offset(&self, free_space: u32) -> u32 {
+        match self {
+            Alignment::Start => 0,
+            Alignment::Center => free_space / 2,
+            Alignment::End => free_space,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    // An alpha below 255 pads with transparency
+    pub background: [u8; 4],
+    pub horizontal: Alignment,
+    pub vertical: Alignment,
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas {
+            width: 1000,
+            height: 1000,
+            background: [255, 255, 255, 255],
+            horizontal: Alignment::Center,
+            vertical: Alignment::Center,
+        }
+    }
+}
+
+// Scale the image to fit inside the canvas, then pad the rest with the background
+pub fn fit_and_pad(img: DynamicImage, canvas: &Canvas) -> DynamicImage {
+    let canvas_width = canvas.width.max(1);
+    let canvas_height = canvas.height.max(1);
+
+    let scale = (canvas_width as f64 / img.width().max(1) as f64)
+        .min(canvas_height as f64 / img.height().max(1) as f64);
+    let width = ((img.width() as f64 * scale).round() as u32).clamp(1, canvas_width);
+    let height = ((img.height() as f64 * scale).round() as u32).clamp(1, canvas_height);
+
+    let color = img.color();
+    let fitted = if (width, height) == (img.width(), img.height()) {
+        img.into_rgba32f()
+    } else {
+        img.resize_exact(width, height, imageops::FilterType::Lanczos3)
+            .into_rgba32f()
+    };
+
+    let background = Rgba(canvas.background.map(|c| c as f32 / 255.0));
+    let mut padded = Rgba32FImage::from_pixel(canvas_width, canvas_height, background);
+    let x = canvas.horizontal.offset(canvas_width - width);
+    let y = canvas.vertical.offset(canvas_height - height);
+    imageops::overlay(&mut padded, &fitted, x as i64, y as i64);
+
+    // Transparent padding needs an alpha channel even if the source had none
+    let color = if canvas.background[3] < 255 {
+        with_alpha(color)
+    } else {
+        color
+    };
+    restore_color_type(padded, color)
+}
+
+fn with_alpha(color: ColorType) -> ColorType {
+    match color {
+        ColorType::L8 => ColorType::La8,
+        ColorType::Rgb8 => ColorType::Rgba8,
+        ColorType::L16 => ColorType::La16,
+        ColorType::Rgb16 => ColorType::Rgba16,
+        ColorType::Rgb32F => ColorType::Rgba32F,
+        other => other,
+    }
+}
@@ -0,0 +1,240 @@
+use image::{DynamicImage, GrayImage, ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba};
+
+use super::quantize::{Dither, IndexedImage, BAYER_8X8};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputColorType {
+    Rgb8,
+    Rgba8,
+    L8,
+    La8,
+    Rgb16,
+    Rgba16,
+    Bit1,
+}
+
+impl OutputColorType {
+    pub fn name(&self) -> &str {
+        match self {
+            OutputColorType::Rgb8 => "RGB 8-bit",
+            OutputColorType::Rgba8 => "RGBA 8-bit",
+            OutputColorType::L8 => "grayscale 8-bit",
+            OutputColorType::La8 => "grayscale+alpha 8-bit",
+            OutputColorType::Rgb16 => "RGB 16-bit",
+            OutputColorType::Rgba16 => "RGBA 16-bit",
+            OutputColorType::Bit1 => "1-bit black and white",
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        matches!(
+            self,
+            OutputColorType::Rgba8 | OutputColorType::La8 | OutputColorType::Rgba16
+        )
+    }
+
+    pub fn is_16_bit(&self) -> bool {
+        matches!(self, OutputColorType::Rgb16 | OutputColorType::Rgba16)
+    }
+}
+
+pub fn convert_color(img: &DynamicImage, target: OutputColorType, dither: Dither) -> DynamicImage {
+    // Only sources with more than 8 bits per channel lose precision here
+    let high_depth = img.color().bytes_per_pixel() / img.color().channel_count() > 1;
+    let depth_dither = if high_depth { dither } else { Dither::None };
+
+    match target {
+        OutputColorType::Rgb16 => DynamicImage::ImageRgb16(img.to_rgb16()),
+        OutputColorType::Rgba16 => DynamicImage::ImageRgba16(img.to_rgba16()),
+        OutputColorType::Rgb8 if depth_dither != Dither::None => DynamicImage::ImageRgb8(
+            reduce_depth::<Rgb<u16>, Rgb<u8>>(&img.to_rgb16(), depth_dither),
+        ),
+        OutputColorType::Rgba8 if depth_dither != Dither::None => DynamicImage::ImageRgba8(
+            reduce_depth::<Rgba<u16>, Rgba<u8>>(&img.to_rgba16(), depth_dither),
+        ),
+        OutputColorType::L8 if depth_dither != Dither::None => DynamicImage::ImageLuma8(
+            reduce_depth::<Luma<u16>, Luma<u8>>(&img.to_luma16(), depth_dither),
+        ),
+        OutputColorType::La8 if depth_dither != Dither::None => DynamicImage::ImageLumaA8(
+            reduce_depth::<LumaA<u16>, LumaA<u8>>(&img.to_luma_alpha16(), depth_dither),
+        ),
+        OutputColorType::Rgb8 => DynamicImage::ImageRgb8(img.to_rgb8()),
+        OutputColorType::Rgba8 => DynamicImage::ImageRgba8(img.to_rgba8()),
+        OutputColorType::L8 => DynamicImage::ImageLuma8(img.to_luma8()),
+        OutputColorType::La8 => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+        // Bilevel output always dithers from the source, regardless of depth
+        OutputColorType::Bit1 => DynamicImage::ImageLuma8(to_bilevel(img, dither)),
+    }
+}
+
+// Wrap a bilevel image as a two-entry palette so PNG output can use 1 bit per pixel
+pub fn bilevel_to_indexed(img: &GrayImage) -> IndexedImage {
+    IndexedImage {
+        width: img.width(),
+        height: img.height(),
+        palette: vec![[0, 0, 0, 255], [255, 255, 255, 255]],
+        indices: img.pixels().map(|p| (p[0] >= 128) as u8).collect(),
+    }
+}
+
+fn reduce_depth<From, To>(
+    img: &ImageBuffer<From, Vec<u16>>,
+    dither: Dither,
+) -> ImageBuffer<To, Vec<u8>>
+where
+    From: Pixel<Subpixel = u16>,
+    To: Pixel<Subpixel = u8>,
+{
+    let (width, height) = img.dimensions();
+    let channels = From::CHANNEL_COUNT as usize;
+    let samples: Vec<f32> = img.as_raw().iter().map(|&v| v as f32 / 257.0).collect();
+    let reduced = dither_samples(&samples, width as usize, channels, dither, 1.0, |v| {
+        v.round().clamp(0.0, 255.0)
+    });
+
+    ImageBuffer::from_raw(width, height, reduced).expect("buffer size matches dimensions")
+}
+
+fn to_bilevel(img: &DynamicImage, dither: Dither) -> GrayImage {
+    let luma = img.to_luma16();
+    let (width, height) = luma.dimensions();
+    let samples: Vec<f32> = luma.as_raw().iter().map(|&v| v as f32 / 257.0).collect();
+    let bilevel = dither_samples(&samples, width as usize, 1, dither, 255.0, |v| {
+        if v >= 128.0 {
+            255.0
+        } else {
+            0.0
+        }
+    });
+
+    GrayImage::from_raw(width, height, bilevel).expect("buffer size matches dimensions")
+}
+
+// Runs a per-sample quantizer over interleaved samples, diffusing or
+// offsetting the rounding error according to the dither mode. `step` is the
+// distance between two adjacent output levels.
+fn dither_samples(
+    samples: &[f32],
+    width: usize,
+    channels: usize,
+    dither: Dither,
+    step: f32,
+    quantize: impl Fn(f32) -> f32,
+) -> Vec<u8> {
+    let row_len = width * channels;
+    let mut output = Vec::with_capacity(samples.len());
+
+    match dither {
+        Dither::None => {
+            output.extend(samples.iter().map(|&v| quantize(v) as u8));
+        }
+        Dither::Ordered => {
+            for (i, &v) in samples.iter().enumerate() {
+                let x = (i % row_len) / channels;
+                let y = i / row_len.max(1);
+                let threshold = BAYER_8X8[y % 8][x % 8] as f32 / 64.0 - 0.5;
+                output.push(quantize((v + threshold * step).clamp(0.0, 255.0)) as u8);
+            }
+        }
+        Dither::FloydSteinberg => {
+            let mut current = vec![0f32; row_len + 2 * channels];
+            let mut next = vec![0f32; row_len + 2 * channels];
+            for row in samples.chunks(row_len.max(1)) {
+                for (i, &v) in row.iter().enumerate() {
+                    let at = i + channels;
+                    let value = (v + current[at]).clamp(0.0, 255.0);
+                    let chosen = quantize(value);
+                    output.push(chosen as u8);
+
+                    let error = value - chosen;
+                    current[at + channels] += error * 7.0 / 16.0;
+                    next[at - channels] += error * 3.0 / 16.0;
+                    next[at] += error * 5.0 / 16.0;
+                    next[at + channels] += error / 16.0;
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.iter_mut().for_each(|e| *e = 0.0);
+            }
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::gradient;
+    use super::*;
+    use image::{ColorType, GenericImageView, ImageBuffer};
+
+    const ALL: [OutputColorType; 7] = [
+        OutputColorType::Rgb8,
+        OutputColorType::Rgba8,
+        OutputColorType::L8,
+        OutputColorType::La8,
+        OutputColorType::Rgb16,
+        OutputColorType::Rgba16,
+        OutputColorType::Bit1,
+    ];
+
+    // Every sample halfway between two 8-bit levels
+    fn between_levels() -> DynamicImage {
+        let value = 100 * 257 + 128;
+        DynamicImage::ImageRgb16(ImageBuffer::from_pixel(32, 32, Rgb([value, value, value])))
+    }
+
+    fn mean(samples: &[u8]) -> f64 {
+        samples.iter().map(|&v| v as f64).sum::<f64>() / samples.len() as f64
+    }
+
+    #[test]
+    fn converts_to_every_color_type() {
+        let img = DynamicImage::ImageRgba8(gradient(16, 16));
+        for target in ALL {
+            let converted = convert_color(&img, target, Dither::FloydSteinberg);
+            let expected = match target {
+                OutputColorType::Rgb8 => ColorType::Rgb8,
+                OutputColorType::Rgba8 => ColorType::Rgba8,
+                OutputColorType::L8 | OutputColorType::Bit1 => ColorType::L8,
+                OutputColorType::La8 => ColorType::La8,
+                OutputColorType::Rgb16 => ColorType::Rgb16,
+                OutputColorType::Rgba16 => ColorType::Rgba16,
+            };
+            assert_eq!(converted.color(), expected, "{}", target.name());
+            assert_eq!(converted.color().has_alpha(), target.has_alpha());
+            assert_eq!(converted.dimensions(), (16, 16));
+        }
+    }
+
+    #[test]
+    fn depth_reduction_dithers_only_deep_sources() {
+        let plain = convert_color(&between_levels(), OutputColorType::Rgb8, Dither::None);
+        assert!(plain.as_bytes().iter().all(|&v| v == plain.as_bytes()[0]));
+
+        // Dithering mixes both neighbouring levels and keeps the average
+        for dither in [Dither::FloydSteinberg, Dither::Ordered] {
+            let dithered = convert_color(&between_levels(), OutputColorType::Rgb8, dither);
+            let bytes = dithered.as_bytes();
+            assert!(bytes.iter().all(|&v| v == 100 || v == 101));
+            assert!((mean(bytes) - 100.5).abs() < 0.1, "{:?}", dither);
+        }
+
+        // 8-bit sources have nothing to dither away
+        let img = DynamicImage::ImageRgba8(gradient(16, 16));
+        let converted = convert_color(&img, OutputColorType::Rgba8, Dither::FloydSteinberg);
+        assert_eq!(converted.as_bytes(), img.as_bytes());
+    }
+
+    #[test]
+    fn bilevel_output_is_black_and_white() {
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(32, 32, Luma([128])));
+        let bilevel = convert_color(&gray, OutputColorType::Bit1, Dither::FloydSteinberg);
+        let bytes = bilevel.as_bytes();
+        assert!(bytes.iter().all(|&v| v == 0 || v == 255));
+        assert!((mean(bytes) - 128.0).abs() < 8.0);
+
+        let indexed = bilevel_to_indexed(bilevel.as_luma8().unwrap());
+        assert_eq!(indexed.palette.len(), 2);
+        assert!(indexed.indices.iter().all(|&i| i <= 1));
+    }
+}
@@ -0,0 +1,499 @@
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+use super::transform::check_canvas;
+use super::{
+    check_color_type, check_extension, convert_batch_targets, convert_image_targets,
+    output_path_in, BatchSummary, ClaimedNames, ConvertFormat, ConvertOptions, ConvertedFile,
+    DecodeLimits, OutputTarget, ProgressEvent, Resize, Transform,
+};
+
+// What happens when an output's file name is already taken
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Collision {
+    // Append _converted_N, keeping the existing file
+    #[default]
+    Number,
+    // Name the output after the input and replace whatever is there
+    Overwrite,
+    // Name the output after the input and leave it out if it exists, for
+    // runs that only pick up new files
+    Skip,
+}
+
+// One checked configuration shared by single and batch conversions
+#[derive(Debug, Clone)]
+pub struct Converter {
+    targets: Vec<OutputTarget>,
+    options: ConvertOptions,
+    output_dir: Option<PathBuf>,
+    collision: Collision,
+}
+
+impl Converter {
+    pub fn builder() -> ConverterBuilder {
+        ConverterBuilder::default()
+    }
+
+    pub fn targets(&self) -> &[OutputTarget] {
+        &self.targets
+    }
+
+    pub fn options(&self) -> &ConvertOptions {
+        &self.options
+    }
+
+    // Where `format` output for `input_path` would be written right now,
+    // None when it exists and collisions are skipped
+    pub fn output_path(&self, input_path: &Path, format: &ConvertFormat) -> Option<PathBuf> {
+        self.claim_output_path(input_path, format, &ClaimedNames::default())
+    }
+
+    // `output_path`, avoiding names already given to other outputs. Skipping
+    // goes by the name after the input so a rerun skips the same files.
+    fn claim_output_path(
+        &self,
+        input_path: &Path,
+        format: &ConvertFormat,
+        claimed: &ClaimedNames,
+    ) -> Option<PathBuf> {
+        let base_path = match &self.output_dir {
+            Some(dir) => dir.as_path(),
+            None => input_path.parent().unwrap_or(Path::new(".")),
+        };
+        if self.collision == Collision::Skip
+            && output_path_in(base_path, input_path, format, true, &HashSet::new()).exists()
+        {
+            return None;
+        }
+        Some(claimed.claim(
+            base_path,
+            input_path,
+            format,
+            self.collision != Collision::Number,
+        ))
+    }
+
+    // Writes every target. Fails with the first target's error if any of
+    // them could not be written, the others are still kept. Skipped targets
+    // are left out of the outputs.
+    pub fn convert_one(&self, input_path: &Path) -> Result<ConvertedFile, Error> {
+        // Targets sharing a format still get a file each
+        let claimed = ClaimedNames::default();
+        let outputs: Vec<(PathBuf, OutputTarget)> = self
+            .targets
+            .iter()
+            .filter_map(|target| {
+                Some((
+                    self.claim_output_path(input_path, &target.format, &claimed)?,
+                    target.clone(),
+                ))
+            })
+            .collect();
+        if outputs.is_empty() {
+            return Ok(ConvertedFile {
+                input: input_path.to_path_buf(),
+                outputs: Vec::new(),
+                rule: None,
+                extension_mismatch: None,
+            });
+        }
+
+        let results = convert_image_targets(input_path.to_path_buf(), &outputs, &self.options)?;
+        let mut written = Vec::new();
+        for ((output_path, _), result) in outputs.into_iter().zip(results) {
+            written.push((output_path, result?));
+        }
+
+        Ok(ConvertedFile {
+            input: input_path.to_path_buf(),
+            outputs: written,
+            rule: None,
+            extension_mismatch: check_extension(input_path, &self.options),
+        })
+    }
+
+    pub fn convert_many(
+        &self,
+        files: Vec<PathBuf>,
+        progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    ) -> BatchSummary {
+        convert_batch_targets(
+            files,
+            &self.targets,
+            &self.options,
+            progress_callback,
+            |input, format, claimed| self.claim_output_path(input, format, claimed),
+        )
+    }
+}
+
+// An output as it was asked for, kept in call order
+#[derive(Debug, Clone)]
+enum PlannedOutput {
+    // Encoded with the builder's settings as they are on build
+    Format(ConvertFormat),
+    Target(OutputTarget),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConverterBuilder {
+    outputs: Vec<PlannedOutput>,
+    options: ConvertOptions,
+    output_dir: Option<PathBuf>,
+    collision: Collision,
+}
+
+impl ConverterBuilder {
+    // Starts from existing options, e.g. ones filled in by the GUI. Settings
+    // made on the builder afterwards change them further.
+    pub fn options(mut self, options: ConvertOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    // Adds an output format, encoded with the builder's quality. Call again
+    // to write several formats per input.
+    pub fn format(mut self, format: ConvertFormat) -> Self {
+        let added = self
+            .outputs
+            .iter()
+            .any(|output| matches!(output, PlannedOutput::Format(f) if *f == format));
+        if !added {
+            self.outputs.push(PlannedOutput::Format(format));
+        }
+        self
+    }
+
+    // Adds an output with its own encoder settings
+    pub fn target(mut self, target: OutputTarget) -> Self {
+        self.outputs.push(PlannedOutput::Target(target));
+        self
+    }
+
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.options.encoder.quality = Some(quality);
+        self
+    }
+
+    // Fits the image into the box, 0 leaves that side unconstrained
+    pub fn resize(self, max_width: u32, max_height: u32) -> Self {
+        self.transform(Transform::Resize(Resize {
+            max_width,
+            max_height,
+            sharpen: None,
+        }))
+    }
+
+    // Transforms run in the order they were added
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.options.transforms.push(transform);
+        self
+    }
+
+    // Leaves EXIF, ICC profiles and other metadata of the input out of the
+    // outputs. The encoders only ever write pixels, so this always holds, the
+    // call just states it.
+    pub fn strip_metadata(self) -> Self {
+        self
+    }
+
+    // Write outputs here instead of next to each input, created on build
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    pub fn collision(mut self, collision: Collision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn limits(mut self, limits: DecodeLimits) -> Self {
+        self.options.limits = limits;
+        self
+    }
+
+    // Only applies to convert_many
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    // Receives the progress of convert_many
+    pub fn events(mut self, events: Sender<ProgressEvent>) -> Self {
+        self.options.events = Some(events);
+        self
+    }
+
+    // JPEG is written when no format or target was given
+    pub fn build(self) -> Result<Converter, Error> {
+        let ConverterBuilder {
+            outputs,
+            options,
+            output_dir,
+            collision,
+        } = self;
+
+        if let Some(quality) = options.encoder.quality {
+            if !(1..=100).contains(&quality) {
+                return Err(invalid(format!("Quality must be 1-100, got {}", quality)));
+            }
+        }
+
+        let mut targets: Vec<OutputTarget> = outputs
+            .into_iter()
+            .map(|output| match output {
+                PlannedOutput::Format(format) => OutputTarget {
+                    format,
+                    encoder: options.encoder.clone(),
+                },
+                PlannedOutput::Target(target) => target,
+            })
+            .collect();
+        if targets.is_empty() {
+            targets.push(OutputTarget {
+                format: ConvertFormat::Jpeg,
+                encoder: options.encoder.clone(),
+            });
+        }
+        for target in &targets {
+            if let Some(quality) = target.encoder.quality {
+                if !(1..=100).contains(&quality) {
+                    return Err(invalid(format!(
+                        "{} quality must be 1-100, got {}",
+                        target.format.name(),
+                        quality
+                    )));
+                }
+            }
+        }
+        for transform in &options.transforms {
+            match transform {
+                Transform::Resize(resize) if resize.max_width == 0 && resize.max_height == 0 => {
+                    return Err(invalid("Resize needs a width or a height".to_string()));
+                }
+                Transform::FitAndPad(canvas) => check_canvas(canvas, &options.limits)?,
+                _ => {}
+            }
+        }
+        if options.timeout.is_some_and(|t| t.is_zero()) {
+            return Err(invalid("Timeout must be above zero".to_string()));
+        }
+
+        for target in &targets {
+            check_color_type(&target.format, &options)?;
+        }
+
+        if let Some(dir) = &output_dir {
+            std::fs::create_dir_all(dir).map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!("Cannot use {} as output folder: {}", dir.display(), e),
+                )
+            })?;
+        }
+
+        Ok(Converter {
+            targets,
+            options,
+            output_dir,
+            collision,
+        })
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{gradient, temp_dir, write_png};
+    use super::super::{EncoderSettings, OutputColorType};
+    use super::*;
+
+    fn error_kind(builder: ConverterBuilder) -> ErrorKind {
+        builder.build().err().unwrap().kind()
+    }
+
+    #[test]
+    fn build_rejects_invalid_settings() {
+        let builder = Converter::builder;
+        assert_eq!(error_kind(builder().quality(0)), ErrorKind::InvalidInput);
+        assert_eq!(error_kind(builder().quality(101)), ErrorKind::InvalidInput);
+        let target = OutputTarget {
+            format: ConvertFormat::Webp,
+            encoder: EncoderSettings {
+                quality: Some(0),
+                ..EncoderSettings::default()
+            },
+        };
+        assert_eq!(
+            error_kind(builder().target(target)),
+            ErrorKind::InvalidInput
+        );
+        assert_eq!(error_kind(builder().resize(0, 0)), ErrorKind::InvalidInput);
+        assert_eq!(
+            error_kind(builder().timeout(Duration::ZERO)),
+            ErrorKind::InvalidInput
+        );
+
+        // JPEG is the default output and can't hold alpha
+        let options = ConvertOptions {
+            color_type: Some(OutputColorType::Rgba8),
+            ..ConvertOptions::default()
+        };
+        assert_eq!(
+            error_kind(builder().options(options)),
+            ErrorKind::Unsupported
+        );
+    }
+
+    #[test]
+    fn build_keeps_outputs_in_call_order() {
+        let converter = Converter::builder()
+            .format(ConvertFormat::Png)
+            .target(OutputTarget::new(ConvertFormat::Avif))
+            .format(ConvertFormat::Webp)
+            .format(ConvertFormat::Png)
+            .quality(70)
+            .build()
+            .unwrap();
+        let formats: Vec<&ConvertFormat> = converter.targets().iter().map(|t| &t.format).collect();
+        assert_eq!(
+            formats,
+            [
+                &ConvertFormat::Png,
+                &ConvertFormat::Avif,
+                &ConvertFormat::Webp
+            ]
+        );
+        // Set after the format, still applied to it
+        assert_eq!(converter.targets()[2].encoder.quality, Some(70));
+        assert_eq!(converter.targets()[1].encoder.quality, None);
+
+        let converter = Converter::builder().build().unwrap();
+        assert_eq!(converter.targets()[0].format, ConvertFormat::Jpeg);
+    }
+
+    #[test]
+    fn build_creates_the_output_folder() {
+        let dir = temp_dir("builder_output_dir").join("nested").join("out");
+        Converter::builder().output_dir(&dir).build().unwrap();
+        assert!(dir.is_dir());
+    }
+
+    fn converter(collision: Collision) -> Converter {
+        Converter::builder()
+            .format(ConvertFormat::Bmp)
+            .collision(collision)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn number_keeps_existing_files() {
+        let dir = temp_dir("collision_number");
+        let input = write_png(&dir, "photo.png", 4, 4);
+        std::fs::write(dir.join("photo_converted_1.bmp"), b"keep").unwrap();
+
+        let converted = converter(Collision::Number).convert_one(&input).unwrap();
+        assert_eq!(converted.outputs[0].0, dir.join("photo_converted_2.bmp"));
+        assert_eq!(
+            std::fs::read(dir.join("photo_converted_1.bmp")).unwrap(),
+            b"keep"
+        );
+    }
+
+    #[test]
+    fn overwrite_replaces_existing_files() {
+        let dir = temp_dir("collision_overwrite");
+        let input = write_png(&dir, "photo.png", 4, 4);
+        std::fs::write(dir.join("photo.bmp"), b"old").unwrap();
+
+        let converted = converter(Collision::Overwrite).convert_one(&input).unwrap();
+        assert_eq!(converted.outputs[0].0, dir.join("photo.bmp"));
+        assert_ne!(std::fs::read(dir.join("photo.bmp")).unwrap(), b"old");
+    }
+
+    #[test]
+    fn skip_leaves_existing_files_alone() {
+        let dir = temp_dir("collision_skip");
+        let done = write_png(&dir, "done.png", 4, 4);
+        let new = write_png(&dir, "new.png", 4, 4);
+        std::fs::write(dir.join("done.bmp"), b"old").unwrap();
+
+        let converter = converter(Collision::Skip);
+        assert_eq!(converter.output_path(&done, &ConvertFormat::Bmp), None);
+        assert!(converter.convert_one(&done).unwrap().outputs.is_empty());
+
+        let summary = converter.convert_many(vec![done.clone(), new], |_, _| {});
+        assert_eq!(summary.skipped, [done]);
+        assert_eq!(summary.success_count, 1);
+        assert_eq!(std::fs::read(dir.join("done.bmp")).unwrap(), b"old");
+        assert!(dir.join("new.bmp").exists());
+    }
+
+    #[test]
+    fn one_folder_for_inputs_sharing_a_name() {
+        let root = temp_dir("output_dir_same_name");
+        let out = root.join("out");
+        let mut files = Vec::new();
+        for folder in ["a", "b"] {
+            std::fs::create_dir(root.join(folder)).unwrap();
+            files.push(write_png(&root.join(folder), "photo.png", 4, 4));
+        }
+
+        let converter = Converter::builder()
+            .format(ConvertFormat::Bmp)
+            .output_dir(&out)
+            .collision(Collision::Overwrite)
+            .build()
+            .unwrap();
+        let summary = converter.convert_many(files, |_, _| {});
+        assert_eq!(summary.success_count, 2);
+        assert!(out.join("photo.bmp").exists());
+        assert!(out.join("photo_converted_1.bmp").exists());
+    }
+
+    #[test]
+    fn outputs_carry_no_metadata() {
+        let dir = temp_dir("strip_metadata");
+        // A JPEG with an EXIF block right after its start marker
+        let mut plain = Vec::new();
+        image::DynamicImage::ImageRgba8(gradient(8, 8))
+            .to_rgb8()
+            .write_to(
+                &mut std::io::Cursor::new(&mut plain),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        let exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\0";
+        let mut jpeg = plain[..2].to_vec();
+        jpeg.extend([0xFF, 0xE1, 0, exif.len() as u8 + 2]);
+        jpeg.extend(exif);
+        jpeg.extend(&plain[2..]);
+        let input = dir.join("tagged.jpg");
+        std::fs::write(&input, jpeg).unwrap();
+
+        let converter = Converter::builder()
+            .format(ConvertFormat::Jpeg)
+            .format(ConvertFormat::Png)
+            .format(ConvertFormat::Webp)
+            .strip_metadata()
+            .build()
+            .unwrap();
+        for (output, _) in converter.convert_one(&input).unwrap().outputs {
+            let bytes = std::fs::read(&output).unwrap();
+            assert!(
+                !bytes.windows(4).any(|w| w == b"Exif"),
+                "{}",
+                output.display()
+            );
+        }
+    }
+}
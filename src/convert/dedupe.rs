@@ -0,0 +1,231 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbaImage};
+use std::path::PathBuf;
+
+use super::{catch_panic, decode_image, map_files, DecodeLimits};
+
+// Bits two images may differ by, averaged over both hashes, and still count
+// as duplicates
+pub const DEFAULT_MAX_DISTANCE: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageHashes {
+    // Gradient hash, good at near-identical crops and re-encodes
+    pub dhash: u64,
+    // DCT hash, robust to scaling and mild color changes
+    pub phash: u64,
+}
+
+impl ImageHashes {
+    pub fn of(img: &DynamicImage) -> Self {
+        ImageHashes {
+            dhash: dhash(img),
+            phash: phash(img),
+        }
+    }
+
+    // Averaging lets one hash vouch for the other, pHash is unstable on
+    // images with little low-frequency detail
+    pub fn is_similar(&self, other: &ImageHashes, max_distance: u32) -> bool {
+        let distance =
+            (self.dhash ^ other.dhash).count_ones() + (self.phash ^ other.phash).count_ones();
+        distance <= 2 * max_distance
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    // The largest image comes first as the one to keep
+    pub files: Vec<PathBuf>,
+    // One per file, in the same order, when they were asked for
+    pub thumbnails: Vec<RgbaImage>,
+}
+
+// Groups visually similar files. Files that cannot be decoded are left out
+// and fail later in the conversion itself.
+pub fn find_duplicates(
+    files: &[PathBuf],
+    max_distance: u32,
+    limits: &DecodeLimits,
+) -> Vec<DuplicateGroup> {
+    group_duplicates(files, max_distance, None, limits)
+}
+
+// `find_duplicates`, also scaling every grouped image to fit a `size` square
+// while it is decoded for hashing
+pub fn find_duplicates_with_thumbnails(
+    files: &[PathBuf],
+    max_distance: u32,
+    size: u32,
+    limits: &DecodeLimits,
+) -> Vec<DuplicateGroup> {
+    group_duplicates(files, max_distance, Some(size), limits)
+}
+
+fn group_duplicates(
+    files: &[PathBuf],
+    max_distance: u32,
+    thumbnail_size: Option<u32>,
+    limits: &DecodeLimits,
+) -> Vec<DuplicateGroup> {
+    // By position in `hashed`
+    let mut thumbnails: Vec<Option<RgbaImage>> = Vec::new();
+    // (index into files, pixel count, file size, hashes)
+    let hashed: Vec<(usize, u64, u64, ImageHashes)> = map_files(
+        files,
+        limits,
+        &[],
+        |_, _| {},
+        |path, _reservation| {
+            let (img, _) = catch_panic(|| decode_image(path, limits)).ok()?;
+            let pixels = img.width() as u64 * img.height() as u64;
+            let bytes = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+            let thumbnail = thumbnail_size.map(|size| img.thumbnail(size, size).to_rgba8());
+            Some((pixels, bytes, ImageHashes::of(&img), thumbnail))
+        },
+    )
+    .into_iter()
+    .enumerate()
+    .filter_map(|(index, hashed)| {
+        hashed.map(|(pixels, bytes, hashes, thumbnail)| {
+            thumbnails.push(thumbnail);
+            (index, pixels, bytes, hashes)
+        })
+    })
+    .collect();
+
+    // Union-find over every similar pair
+    let mut parent: Vec<usize> = (0..hashed.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for a in 0..hashed.len() {
+        for b in a + 1..hashed.len() {
+            if hashed[a].3.is_similar(&hashed[b].3, max_distance) {
+                let (root_a, root_b) = (root(&mut parent, a), root(&mut parent, b));
+                parent[root_b.max(root_a)] = root_a.min(root_b);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_root = vec![usize::MAX; hashed.len()];
+    for i in 0..hashed.len() {
+        let r = root(&mut parent, i);
+        if group_of_root[r] == usize::MAX {
+            group_of_root[r] = groups.len();
+            groups.push(Vec::new());
+        }
+        groups[group_of_root[r]].push(i);
+    }
+
+    groups
+        .into_iter()
+        .filter(|members| members.len() > 1)
+        .map(|mut members| {
+            // Most pixels first, then the larger file, then batch order
+            members.sort_by_key(|&i| {
+                let (index, pixels, bytes, _) = hashed[i];
+                (std::cmp::Reverse(pixels), std::cmp::Reverse(bytes), index)
+            });
+            DuplicateGroup {
+                files: members
+                    .iter()
+                    .map(|&i| files[hashed[i].0].clone())
+                    .collect(),
+                thumbnails: members
+                    .iter()
+                    .filter_map(|&i| thumbnails[i].take())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn dhash(img: &DynamicImage) -> u64 {
+    let small = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    hash
+}
+
+fn phash(img: &DynamicImage) -> u64 {
+    const SIZE: usize = 32;
+    const LOW: usize = 8;
+
+    let small = img
+        .resize_exact(SIZE as u32, SIZE as u32, FilterType::Triangle)
+        .to_luma8();
+    let pixels: Vec<f64> = small.as_raw().iter().map(|&v| v as f64).collect();
+
+    // Only the lowest frequencies of the DCT are needed
+    let cosines: Vec<f64> = (0..LOW * SIZE)
+        .map(|i| {
+            let (u, x) = (i / SIZE, i % SIZE);
+            ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2 * SIZE) as f64).cos()
+        })
+        .collect();
+
+    let mut coefficients = Vec::with_capacity(LOW * LOW);
+    for v in 0..LOW {
+        for u in 0..LOW {
+            let mut sum = 0.0;
+            for y in 0..SIZE {
+                let row = &pixels[y * SIZE..(y + 1) * SIZE];
+                let row_sum: f64 = row
+                    .iter()
+                    .enumerate()
+                    .map(|(x, p)| p * cosines[u * SIZE + x])
+                    .sum();
+                sum += row_sum * cosines[v * SIZE + y];
+            }
+            coefficients.push(sum);
+        }
+    }
+
+    // The DC term only carries overall brightness
+    let mut sorted: Vec<f64> = coefficients[1..].to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    coefficients
+        .iter()
+        .fold(0u64, |hash, &c| (hash << 1) | (c > median) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::*;
+
+    #[test]
+    fn thumbnails_come_with_their_group() {
+        let dir = temp_dir("dedupe_thumbnails");
+        let files = vec![
+            write_png(&dir, "photo.png", 32, 32),
+            write_png(&dir, "copy.png", 32, 32),
+        ];
+        let limits = DecodeLimits::default();
+
+        let groups = find_duplicates_with_thumbnails(&files, DEFAULT_MAX_DISTANCE, 16, &limits);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].files, files);
+        assert_eq!(groups[0].thumbnails.len(), 2);
+        assert!(groups[0]
+            .thumbnails
+            .iter()
+            .all(|thumbnail| thumbnail.dimensions() == (16, 16)));
+
+        let groups = find_duplicates(&files, DEFAULT_MAX_DISTANCE, &limits);
+        assert!(groups[0].thumbnails.is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
+use image::{imageops, DynamicImage};
+use std::io::{Cursor, Error};
+
+use super::{color, quantize, ConvertFormat, ConvertOptions, OutputColorType, OutputTarget};
+
+#[derive(Debug, Clone)]
+pub struct EncoderSettings {
+    // Lossy quality 1..=100 for JPEG, WebP and AVIF. Unset keeps each
+    // encoder's default, which for WebP means lossless.
+    pub quality: Option<u8>,
+    // 1 is slowest and smallest, 10 is fastest
+    pub avif_speed: u8,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        EncoderSettings {
+            quality: None,
+            avif_speed: 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SizeTarget {
+    pub max_bytes: u64,
+    // Lowest quality the search may fall back to before giving up or downscaling
+    pub min_quality: u8,
+    pub allow_downscale: bool,
+}
+
+impl Default for SizeTarget {
+    fn default() -> Self {
+        SizeTarget {
+            max_bytes: 200 * 1024,
+            min_quality: 10,
+            allow_downscale: false,
+        }
+    }
+}
+
+pub struct Encoded {
+    pub bytes: Vec<u8>,
+    pub quality: Option<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn encode_image(
+    img: &DynamicImage,
+    target: &OutputTarget,
+    options: &ConvertOptions,
+) -> Result<Encoded, Error> {
+    let format = &target.format;
+    let encoder = &target.encoder;
+    // Formats without a quality setting can't be fitted, they keep their size
+    match options
+        .size_target
+        .as_ref()
+        .filter(|_| format.supports_quality())
+    {
+        Some(size_target) => encode_within_budget(img, format, encoder, options, size_target),
+        None => {
+            let quality = encoder.quality.filter(|_| format.supports_quality());
+            Ok(Encoded {
+                bytes: encode_with_quality(img, format, encoder, options, quality)?,
+                quality,
+                width: img.width(),
+                height: img.height(),
+            })
+        }
+    }
+}
+
+fn encode_within_budget(
+    img: &DynamicImage,
+    format: &ConvertFormat,
+    encoder: &EncoderSettings,
+    options: &ConvertOptions,
+    target: &SizeTarget,
+) -> Result<Encoded, Error> {
+    let max_quality = encoder.quality.unwrap_or(95).clamp(1, 100);
+    let min_quality = target.min_quality.clamp(1, max_quality);
+
+    let mut scaled = img.clone();
+    loop {
+        // Binary search for the highest quality that still fits
+        let mut best = None;
+        let mut smallest =
+            encode_with_quality(&scaled, format, encoder, options, Some(min_quality))?;
+        if smallest.len() as u64 <= target.max_bytes {
+            let (mut low, mut high) = (min_quality, max_quality);
+            best = Some((min_quality, std::mem::take(&mut smallest)));
+            while low < high {
+                let quality = low + (high - low).div_ceil(2);
+                let bytes = encode_with_quality(&scaled, format, encoder, options, Some(quality))?;
+                if bytes.len() as u64 <= target.max_bytes {
+                    low = quality;
+                    best = Some((quality, bytes));
+                } else {
+                    high = quality - 1;
+                }
+            }
+        }
+
+        if let Some((quality, bytes)) = best {
+            return Ok(Encoded {
+                bytes,
+                quality: Some(quality),
+                width: scaled.width(),
+                height: scaled.height(),
+            });
+        }
+
+        // Even the lowest quality is too large, shrink by the estimated overshoot
+        if !target.allow_downscale || scaled.width() <= 16 || scaled.height() <= 16 {
+            return Err(Error::other(format!(
+                "Could not fit under {} bytes (smallest was {} bytes at quality {})",
+                target.max_bytes,
+                smallest.len(),
+                min_quality
+            )));
+        }
+
+        let factor =
+            ((target.max_bytes as f64 / smallest.len() as f64).sqrt() * 0.95).clamp(0.5, 0.95);
+        let width = ((scaled.width() as f64 * factor) as u32).max(1);
+        let height = ((scaled.height() as f64 * factor) as u32).max(1);
+        scaled = img.resize_exact(width, height, imageops::FilterType::Lanczos3);
+    }
+}
+
+fn encode_with_quality(
+    img: &DynamicImage,
+    format: &ConvertFormat,
+    encoder: &EncoderSettings,
+    options: &ConvertOptions,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::new();
+
+    match (format, quality) {
+        (ConvertFormat::Gif, _) => {
+            let mut rgba = img.to_rgba8();
+            quantize::prepare_for_gif(&mut rgba);
+            let indexed = quantize::quantize(&rgba, &options.quantize);
+            quantize::write_gif(&indexed, &mut bytes)?;
+        }
+        (ConvertFormat::Png, _) if options.color_type == Some(OutputColorType::Bit1) => {
+            let indexed = color::bilevel_to_indexed(&img.to_luma8());
+            quantize::write_indexed_png(&indexed, &mut bytes)?;
+        }
+        (ConvertFormat::Png, _) if options.quantize.indexed_png => {
+            let indexed = quantize::quantize(&img.to_rgba8(), &options.quantize);
+            quantize::write_indexed_png(&indexed, &mut bytes)?;
+        }
+        (ConvertFormat::Jpeg, Some(quality)) => {
+            let encoder = JpegEncoder::new_with_quality(&mut bytes, quality.clamp(1, 100));
+            img.write_with_encoder(encoder).map_err(Error::other)?;
+        }
+        (ConvertFormat::Avif, quality) => {
+            let encoder = AvifEncoder::new_with_speed_quality(
+                &mut bytes,
+                encoder.avif_speed.clamp(1, 10),
+                quality.unwrap_or(80).clamp(1, 100),
+            );
+            img.write_with_encoder(encoder).map_err(Error::other)?;
+        }
+        (ConvertFormat::Webp, Some(quality)) => {
+            // The built-in WebP encoder is lossless only, libwebp handles lossy output
+            let rgba = img.to_rgba8();
+            let encoded = webp::Encoder::from_rgba(&rgba, rgba.width(), rgba.height())
+                .encode_simple(false, quality.clamp(1, 100) as f32)
+                .map_err(|e| Error::other(format!("WebP encoding failed: {:?}", e)))?;
+            bytes.extend_from_slice(&encoded);
+        }
+        _ => {
+            img.write_to(&mut Cursor::new(&mut bytes), format.image_format())
+                .map_err(Error::other)?;
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Noise compresses poorly, so quality makes a real difference in size
+    fn noise(width: u32, height: u32) -> DynamicImage {
+        let mut state = 12345u32;
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |_, _| {
+            let mut next = || {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            };
+            Rgb([next(), next(), next()])
+        }))
+    }
+
+    fn jpeg_size(img: &DynamicImage, quality: u8) -> u64 {
+        let encoder = EncoderSettings::default();
+        let options = ConvertOptions::default();
+        encode_with_quality(img, &ConvertFormat::Jpeg, &encoder, &options, Some(quality))
+            .unwrap()
+            .len() as u64
+    }
+
+    fn options(target: SizeTarget) -> ConvertOptions {
+        ConvertOptions {
+            size_target: Some(target),
+            ..ConvertOptions::default()
+        }
+    }
+
+    #[test]
+    fn finds_the_highest_quality_that_fits() {
+        let img = noise(128, 128);
+        let max_bytes = (jpeg_size(&img, 30) + jpeg_size(&img, 90)) / 2;
+        let options = options(SizeTarget {
+            max_bytes,
+            ..SizeTarget::default()
+        });
+
+        let target = OutputTarget::new(ConvertFormat::Jpeg);
+        let encoded = encode_image(&img, &target, &options).unwrap();
+        let quality = encoded.quality.unwrap();
+        assert!(encoded.bytes.len() as u64 <= max_bytes);
+        assert!(jpeg_size(&img, quality + 1) > max_bytes);
+        assert_eq!((encoded.width, encoded.height), (128, 128));
+    }
+
+    #[test]
+    fn downscales_only_when_allowed() {
+        let img = noise(128, 128);
+        let max_bytes = jpeg_size(&img, 10) / 3;
+        let target = OutputTarget::new(ConvertFormat::Jpeg);
+
+        let fixed = options(SizeTarget {
+            max_bytes,
+            ..SizeTarget::default()
+        });
+        let error = encode_image(&img, &target, &fixed).err().unwrap();
+        assert!(error.to_string().starts_with("Could not fit under"));
+
+        let scalable = options(SizeTarget {
+            max_bytes,
+            allow_downscale: true,
+            ..SizeTarget::default()
+        });
+        let encoded = encode_image(&img, &target, &scalable).unwrap();
+        assert!(encoded.bytes.len() as u64 <= max_bytes);
+        assert!(encoded.width < 128 && encoded.height < 128);
+    }
+
+    #[test]
+    fn formats_without_quality_ignore_the_target() {
+        let img = noise(64, 64);
+        let options = options(SizeTarget {
+            max_bytes: 1,
+            ..SizeTarget::default()
+        });
+        let encoded = encode_image(&img, &OutputTarget::new(ConvertFormat::Png), &options).unwrap();
+        assert_eq!(encoded.quality, None);
+        assert!(encoded.bytes.len() > 1);
+    }
+}
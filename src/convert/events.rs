@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use super::report::{json_optional, json_string};
+use super::{BatchSummary, ConvertOptions, ConvertStats};
+
+// What a batch reports as it goes, sent to `ConvertOptions::events`. Files run
+// in parallel, so events of different files interleave.
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    // Decoding is about to begin, after waiting for room in the memory budget
+    Started {
+        file: PathBuf,
+    },
+    // One per output written
+    Finished {
+        file: PathBuf,
+        output: PathBuf,
+        stats: ConvertStats,
+    },
+    // The whole file or one of its outputs failed
+    Failed {
+        file: PathBuf,
+        output: Option<PathBuf>,
+        error_kind: &'static str,
+        error: String,
+    },
+    // Nothing needed doing, e.g. every output already exists
+    Skipped {
+        file: PathBuf,
+        reason: String,
+    },
+    // Always the last event of a batch
+    BatchDone {
+        summary: BatchSummary,
+    },
+}
+
+impl ProgressEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProgressEvent::Started { .. } => "started",
+            ProgressEvent::Finished { .. } => "finished",
+            ProgressEvent::Failed { .. } => "failed",
+            ProgressEvent::Skipped { .. } => "skipped",
+            ProgressEvent::BatchDone { .. } => "batch_done",
+        }
+    }
+
+    // A single line, for appending to a log
+    pub fn to_json(&self) -> String {
+        let path = |path: &PathBuf| json_string(&path.display().to_string());
+        let mut fields = vec![("event", json_string(self.name()))];
+        match self {
+            ProgressEvent::Started { file } => fields.push(("file", path(file))),
+            ProgressEvent::Finished {
+                file,
+                output,
+                stats,
+            } => fields.extend([
+                ("file", path(file)),
+                ("output", path(output)),
+                ("format", json_string(stats.format.extension())),
+                ("width", stats.width.to_string()),
+                ("height", stats.height.to_string()),
+                ("output_bytes", stats.output_bytes.to_string()),
+                ("quality", json_optional(stats.quality)),
+            ]),
+            ProgressEvent::Failed {
+                file,
+                output,
+                error_kind,
+                error,
+            } => fields.extend([
+                ("file", path(file)),
+                ("output", json_optional(output.as_ref().map(path))),
+                ("error_kind", json_string(error_kind)),
+                ("error", json_string(error)),
+            ]),
+            ProgressEvent::Skipped { file, reason } => {
+                fields.extend([("file", path(file)), ("reason", json_string(reason))])
+            }
+            ProgressEvent::BatchDone { summary } => fields.extend([
+                ("success_count", summary.success_count.to_string()),
+                ("error_count", summary.error_count.to_string()),
+                ("skipped_count", summary.skipped.len().to_string()),
+            ]),
+        }
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("\"{}\": {}", key, value))
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+}
+
+// Events are only built when someone listens. A receiver that has gone away
+// doesn't stop the batch.
+pub(super) fn emit(options: &ConvertOptions, event: impl FnOnce() -> ProgressEvent) {
+    if let Some(events) = &options.events {
+        let _ = events.send(event());
+    }
+}
@@ -0,0 +1,55 @@
+use image::DynamicImage;
+
+use super::adjust::restore_color_type;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsharpMask {
+    // Gaussian sigma of the blurred copy, in pixels
+    pub radius: f32,
+    // How much of the difference to add back, 1.0 doubles local contrast
+    pub amount: f32,
+    // Differences below this many levels (0..=255) are left alone
+    pub threshold: u8,
+}
+
+impl Default for UnsharpMask {
+    fn default() -> Self {
+        UnsharpMask {
+            radius: 1.0,
+            amount: 0.8,
+            threshold: 2,
+        }
+    }
+}
+
+pub fn unsharp_mask(img: DynamicImage, mask: &UnsharpMask) -> DynamicImage {
+    if mask.radius <= 0.0 || mask.amount <= 0.0 {
+        return img;
+    }
+
+    let color = img.color();
+    let mut sharpened = img.into_rgba32f();
+    let blurred = DynamicImage::ImageRgba32F(sharpened.clone())
+        .blur(mask.radius)
+        .into_rgba32f();
+    let threshold = mask.threshold as f32 / 255.0;
+
+    for (pixel, soft) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        // Alpha stays untouched so edges do not gain halos in transparency
+        for c in 0..3 {
+            let difference = pixel[c] - soft[c];
+            if difference.abs() >= threshold {
+                pixel[c] = (pixel[c] + difference * mask.amount).clamp(0.0, 1.0);
+            }
+        }
+    }
+
+    restore_color_type(sharpened, color)
+}
+
+pub fn gaussian_blur(img: DynamicImage, sigma: f32) -> DynamicImage {
+    if sigma <= 0.0 {
+        return img;
+    }
+    img.blur(sigma)
+}
@@ -0,0 +1,507 @@
+use image::codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder};
+use image::{
+    AnimationDecoder, ExtendedColorType, Frames, ImageDecoder, ImageError, ImageFormat,
+    ImageReader, ImageResult,
+};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::report::json_string;
+use super::DecodeLimits;
+
+// What can be learned about a file without decoding its pixels
+#[derive(Debug, Clone)]
+pub struct ImageInfo {
+    pub path: PathBuf,
+    // Detected from the content, not the extension
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub color_type: ExtendedColorType,
+    pub bits_per_channel: u16,
+    pub file_bytes: u64,
+    // 1 for still images
+    pub frame_count: usize,
+    pub icc_profile_bytes: Option<usize>,
+    // Readable EXIF fields in file order, e.g. ("Model", "X100V")
+    pub exif: Vec<(String, String)>,
+}
+
+impl ImageInfo {
+    pub fn format_name(&self) -> &'static str {
+        self.format
+            .extensions_str()
+            .first()
+            .copied()
+            .unwrap_or("unknown")
+    }
+
+    pub fn to_json(&self) -> String {
+        let exif: Vec<String> = self
+            .exif
+            .iter()
+            .map(|(name, value)| format!("{}: {}", json_string(name), json_string(value)))
+            .collect();
+
+        let mut json = String::from("{\n");
+        let _ = writeln!(
+            json,
+            "  \"path\": {},",
+            json_string(&self.path.display().to_string())
+        );
+        let _ = writeln!(json, "  \"format\": {},", json_string(self.format_name()));
+        let _ = writeln!(json, "  \"width\": {},", self.width);
+        let _ = writeln!(json, "  \"height\": {},", self.height);
+        let _ = writeln!(
+            json,
+            "  \"color_type\": {},",
+            json_string(&format!("{:?}", self.color_type))
+        );
+        let _ = writeln!(json, "  \"bits_per_channel\": {},", self.bits_per_channel);
+        let _ = writeln!(json, "  \"file_bytes\": {},", self.file_bytes);
+        let _ = writeln!(json, "  \"frame_count\": {},", self.frame_count);
+        let _ = writeln!(
+            json,
+            "  \"icc_profile_bytes\": {},",
+            self.icc_profile_bytes
+                .map_or("null".to_string(), |b| b.to_string())
+        );
+        let _ = writeln!(json, "  \"exif\": {{{}}}", exif.join(", "));
+        json.push_str("}\n");
+        json
+    }
+}
+
+pub fn inspect(path: &Path, limits: &DecodeLimits) -> Result<ImageInfo, Error> {
+    let mut reader = ImageReader::open(path)?.with_guessed_format()?;
+    reader.limits(limits.image_limits());
+    let format = reader
+        .format()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Unrecognized image format"))?;
+
+    let mut decoder = reader.into_decoder().map_err(|e| match e {
+        ImageError::Limits(e) => Error::new(
+            ErrorKind::OutOfMemory,
+            format!("Image exceeds the decode limits: {}", e),
+        ),
+        e => Error::new(
+            ErrorKind::InvalidData,
+            format!("Could not read header: {}", e),
+        ),
+    })?;
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.original_color_type();
+    let channels = color_type.channel_count().max(1) as u16;
+    // Metadata is optional, a broken chunk shouldn't hide the rest
+    let icc_profile_bytes = decoder.icc_profile().ok().flatten().map(|icc| icc.len());
+    let exif = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .map(|raw| parse_exif(&raw))
+        .unwrap_or_default();
+
+    Ok(ImageInfo {
+        path: path.to_path_buf(),
+        format,
+        width,
+        height,
+        color_type,
+        bits_per_channel: color_type.bits_per_pixel() / channels,
+        file_bytes: std::fs::metadata(path)?.len(),
+        frame_count: count_frames(path, format, limits).unwrap_or(1),
+        icc_profile_bytes,
+        exif,
+    })
+}
+
+// Walks the animation, which decodes every frame, so only formats that can
+// animate are opened again
+fn count_frames(path: &Path, format: ImageFormat, limits: &DecodeLimits) -> Option<usize> {
+    decode_frames(path, format, limits)
+        .ok()
+        .map(|frames| frames.unwrap_or(1))
+}
+
+// Decodes every frame of an animation, failing on the first broken one.
+// Still images are None, only their header is read.
+pub(super) fn decode_frames(
+    path: &Path,
+    format: ImageFormat,
+    limits: &DecodeLimits,
+) -> ImageResult<Option<usize>> {
+    fn count<'a>(frames: Frames<'a>) -> ImageResult<Option<usize>> {
+        frames
+            .map(|frame| frame.map(|_| 1))
+            .sum::<ImageResult<usize>>()
+            .map(Some)
+    }
+
+    let reader = BufReader::new(File::open(path)?);
+    match format {
+        ImageFormat::Gif => {
+            let mut decoder = GifDecoder::new(reader)?;
+            decoder.set_limits(limits.image_limits())?;
+            count(decoder.into_frames())
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::with_limits(reader, limits.image_limits())?;
+            if decoder.is_apng()? {
+                count(decoder.apng()?.into_frames())
+            } else {
+                Ok(None)
+            }
+        }
+        ImageFormat::WebP => {
+            let mut decoder = WebPDecoder::new(reader)?;
+            decoder.set_limits(limits.image_limits())?;
+            if decoder.has_animation() {
+                count(decoder.into_frames())
+            } else {
+                Ok(None)
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+const EXIF_IFD_POINTER: u16 = 0x8769;
+const GPS_IFD_POINTER: u16 = 0x8825;
+
+fn exif_tag_name(tag: u16) -> Option<&'static str> {
+    Some(match tag {
+        0x010F => "Make",
+        0x0110 => "Model",
+        0x0112 => "Orientation",
+        0x0131 => "Software",
+        0x0132 => "DateTime",
+        0x013B => "Artist",
+        0x8298 => "Copyright",
+        0x829A => "ExposureTime",
+        0x829D => "FNumber",
+        0x8827 => "ISO",
+        0x9003 => "DateTimeOriginal",
+        0x920A => "FocalLength",
+        0xA434 => "LensModel",
+        _ => return None,
+    })
+}
+
+// Reads the common fields of a raw TIFF-structured EXIF block. Anything
+// malformed is skipped rather than failing the whole inspection.
+fn parse_exif(raw: &[u8]) -> Vec<(String, String)> {
+    let raw = raw.strip_prefix(b"Exif\0\0").unwrap_or(raw);
+    let little_endian = match raw.get(..4) {
+        Some([b'I', b'I', 42, 0]) => true,
+        Some([b'M', b'M', 0, 42]) => false,
+        _ => return Vec::new(),
+    };
+    let tiff = Tiff { raw, little_endian };
+
+    let mut fields = Vec::new();
+    let Some(ifd0) = tiff.u32_at(4) else {
+        return fields;
+    };
+    let mut sub_ifds = Vec::new();
+    for (tag, value) in tiff.entries(ifd0 as usize) {
+        match tag {
+            EXIF_IFD_POINTER | GPS_IFD_POINTER => {
+                if let Some(offset) = value.first_integer() {
+                    sub_ifds.push((tag, offset as usize));
+                }
+            }
+            _ => push_field(&mut fields, tag, &value),
+        }
+    }
+
+    for (pointer, offset) in sub_ifds {
+        let entries = tiff.entries(offset);
+        if pointer == GPS_IFD_POINTER {
+            if let Some(position) = gps_position(&entries) {
+                fields.push(("GPS".to_string(), position));
+            }
+        } else {
+            for (tag, value) in &entries {
+                push_field(&mut fields, *tag, value);
+            }
+        }
+    }
+    fields
+}
+
+fn push_field(fields: &mut Vec<(String, String)>, tag: u16, value: &ExifValue) {
+    if let Some(name) = exif_tag_name(tag) {
+        let text = match (tag, value) {
+            (0x829A, ExifValue::Rationals(r)) if r.len() == 1 && r[0].0 > 0 => {
+                format!("1/{}", (r[0].1 as f64 / r[0].0 as f64).round())
+            }
+            (0x829D, _) => format!("f/{}", value),
+            (0x920A, _) => format!("{} mm", value),
+            _ => value.to_string(),
+        };
+        if !text.is_empty() {
+            fields.push((name.to_string(), text));
+        }
+    }
+}
+
+// Latitude and longitude in decimal degrees, e.g. "48.858370, 2.294481"
+fn gps_position(entries: &[(u16, ExifValue)]) -> Option<String> {
+    let find = |tag: u16| entries.iter().find(|(t, _)| *t == tag).map(|(_, v)| v);
+    let degrees = |tag: u16, ref_tag: u16, negative: &str| -> Option<f64> {
+        let ExifValue::Rationals(parts) = find(tag)? else {
+            return None;
+        };
+        let mut value = 0.0;
+        for (part, scale) in parts.iter().zip([1.0, 60.0, 3600.0]) {
+            if part.1 == 0 {
+                return None;
+            }
+            value += part.0 as f64 / part.1 as f64 / scale;
+        }
+        let is_negative = matches!(find(ref_tag), Some(ExifValue::Ascii(r)) if r == negative);
+        Some(if is_negative { -value } else { value })
+    };
+    let latitude = degrees(0x0002, 0x0001, "S")?;
+    let longitude = degrees(0x0004, 0x0003, "W")?;
+    Some(format!("{:.6}, {:.6}", latitude, longitude))
+}
+
+enum ExifValue {
+    Ascii(String),
+    Integers(Vec<u32>),
+    // Numerator and denominator
+    Rationals(Vec<(u32, u32)>),
+    Other,
+}
+
+impl ExifValue {
+    fn first_integer(&self) -> Option<u32> {
+        match self {
+            ExifValue::Integers(values) => values.first().copied(),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ExifValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExifValue::Ascii(text) => write!(f, "{}", text),
+            ExifValue::Integers(values) => {
+                let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+                write!(f, "{}", values.join(", "))
+            }
+            ExifValue::Rationals(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|&(n, d)| match d {
+                        0 => "0".to_string(),
+                        1 => n.to_string(),
+                        _ => format!("{}", (n as f64 / d as f64 * 100.0).round() / 100.0),
+                    })
+                    .collect();
+                write!(f, "{}", values.join(", "))
+            }
+            ExifValue::Other => Ok(()),
+        }
+    }
+}
+
+struct Tiff<'a> {
+    raw: &'a [u8],
+    little_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.raw.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.raw.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn entries(&self, offset: usize) -> Vec<(u16, ExifValue)> {
+        let count = self.u16_at(offset).unwrap_or(0) as usize;
+        (0..count)
+            .filter_map(|i| {
+                let entry = offset + 2 + i * 12;
+                let tag = self.u16_at(entry)?;
+                let kind = self.u16_at(entry + 2)?;
+                let count = self.u32_at(entry + 4)? as usize;
+                Some((tag, self.value(kind, count, entry + 8)?))
+            })
+            .collect()
+    }
+
+    // Values over four bytes live elsewhere, the entry holds their offset
+    fn value(&self, kind: u16, count: usize, field: usize) -> Option<ExifValue> {
+        let size = match kind {
+            1 | 2 | 7 => 1,
+            3 => 2,
+            4 => 4,
+            5 => 8,
+            _ => return Some(ExifValue::Other),
+        };
+        let length = size * count;
+        let start = if length > 4 {
+            self.u32_at(field)? as usize
+        } else {
+            field
+        };
+        let bytes = self.raw.get(start..start.checked_add(length)?)?;
+
+        Some(match kind {
+            2 => ExifValue::Ascii(
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string(),
+            ),
+            3 => ExifValue::Integers(
+                (0..count)
+                    .filter_map(|i| self.u16_at(start + i * 2).map(u32::from))
+                    .collect(),
+            ),
+            4 => ExifValue::Integers(
+                (0..count)
+                    .filter_map(|i| self.u32_at(start + i * 4))
+                    .collect(),
+            ),
+            5 => ExifValue::Rationals(
+                (0..count)
+                    .filter_map(|i| {
+                        Some((self.u32_at(start + i * 8)?, self.u32_at(start + i * 8 + 4)?))
+                    })
+                    .collect(),
+            ),
+            _ => ExifValue::Other,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // IFD0 with Make, Orientation and a pointer to an EXIF IFD holding
+    // FNumber and ExposureTime
+    fn exif_block(little_endian: bool) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if little_endian {
+                v.to_le_bytes()
+            } else {
+                v.to_be_bytes()
+            }
+        };
+        let entry = |out: &mut Vec<u8>, tag: u16, kind: u16, count: u32, value: [u8; 4]| {
+            out.extend(u16_bytes(tag));
+            out.extend(u16_bytes(kind));
+            out.extend(u32_bytes(count));
+            out.extend(value);
+        };
+        let short = |v: u16| {
+            let [a, b] = u16_bytes(v);
+            [a, b, 0, 0]
+        };
+
+        let mut out = if little_endian {
+            b"II\x2a\x00".to_vec()
+        } else {
+            b"MM\x00\x2a".to_vec()
+        };
+        out.extend(u32_bytes(8));
+
+        // IFD0 at 8, the Make string at 50
+        out.extend(u16_bytes(3));
+        entry(&mut out, 0x010F, 2, 6, u32_bytes(50));
+        entry(&mut out, 0x0112, 3, 1, short(6));
+        entry(&mut out, EXIF_IFD_POINTER, 4, 1, u32_bytes(56));
+        out.extend(u32_bytes(0));
+        out.extend(b"Canon\0");
+
+        // EXIF IFD at 56, the rationals at 86 and 94
+        out.extend(u16_bytes(2));
+        entry(&mut out, 0x829D, 5, 1, u32_bytes(86));
+        entry(&mut out, 0x829A, 5, 1, u32_bytes(94));
+        out.extend(u32_bytes(0));
+        for value in [28, 10, 1, 250] {
+            out.extend(u32_bytes(value));
+        }
+        out
+    }
+
+    fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn reads_both_byte_orders() {
+        for little_endian in [true, false] {
+            let fields = parse_exif(&exif_block(little_endian));
+            assert_eq!(field(&fields, "Make"), Some("Canon"));
+            assert_eq!(field(&fields, "Orientation"), Some("6"));
+            assert_eq!(field(&fields, "FNumber"), Some("f/2.8"));
+            assert_eq!(field(&fields, "ExposureTime"), Some("1/250"));
+        }
+    }
+
+    #[test]
+    fn skips_the_exif_header() {
+        let mut raw = b"Exif\0\0".to_vec();
+        raw.extend(exif_block(false));
+        assert_eq!(field(&parse_exif(&raw), "Make"), Some("Canon"));
+    }
+
+    #[test]
+    fn truncated_blocks_keep_what_is_there() {
+        let block = exif_block(true);
+        for length in 0..block.len() {
+            parse_exif(&block[..length]);
+        }
+
+        // Cut inside the EXIF IFD, IFD0 is still complete
+        let fields = parse_exif(&block[..70]);
+        assert_eq!(field(&fields, "Make"), Some("Canon"));
+        assert_eq!(field(&fields, "FNumber"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_headers() {
+        assert!(parse_exif(b"XX\x2a\x00\x08\x00\x00\x00").is_empty());
+        assert!(parse_exif(b"").is_empty());
+    }
+
+    #[test]
+    fn offsets_past_the_end_are_ignored() {
+        let mut block = exif_block(true);
+        // Point the Make string far outside the block
+        block[18..22].copy_from_slice(&u32::MAX.to_le_bytes());
+        let fields = parse_exif(&block);
+        assert_eq!(field(&fields, "Make"), None);
+        assert_eq!(field(&fields, "Orientation"), Some("6"));
+    }
+}
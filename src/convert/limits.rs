@@ -0,0 +1,186 @@
+use image::{ImageDecoder, ImageReader, Limits};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::transform::{working_bytes, Transform};
+
+const MIB: u64 = 1024 * 1024;
+
+// Guards against decompression bombs: a few bytes on disk can claim an image
+// of gigapixels
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    // Bytes a single decoded image may take
+    pub max_alloc: u64,
+    // Bytes all images decoded at once by a batch may take together. A file
+    // that timed out keeps its share until its thread ends, files that only
+    // fit once it does fail instead of waiting.
+    pub memory_budget: u64,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_width: 16384,
+            max_height: 16384,
+            max_alloc: 512 * MIB,
+            memory_budget: 2048 * MIB,
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub(super) fn image_limits(&self) -> Limits {
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(self.max_width);
+        limits.max_image_height = Some(self.max_height);
+        limits.max_alloc = Some(self.max_alloc);
+        limits
+    }
+}
+
+// Bytes the decoded image and the working copies of `transforms` will need,
+// read from the header alone. Unreadable files count as free, decoding them
+// fails right away.
+pub(super) fn estimate_peak_bytes(input_path: &Path, transforms: &[Transform]) -> u64 {
+    ImageReader::open(input_path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+        .map_or(0, |decoder| {
+            let (width, height) = decoder.dimensions();
+            decoder.total_bytes() + working_bytes(transforms, width, height)
+        })
+}
+
+// Memory shared by the files a batch decodes in parallel. Threads wait until
+// enough has been released by finished files.
+pub(super) struct MemoryBudget {
+    total: u64,
+    state: Mutex<BudgetState>,
+    released: Condvar,
+}
+
+struct BudgetState {
+    available: u64,
+    // Held by files the batch stopped waiting for, which may never end
+    abandoned: u64,
+}
+
+impl MemoryBudget {
+    pub(super) fn new(total: u64) -> Arc<Self> {
+        Arc::new(MemoryBudget {
+            total,
+            state: Mutex::new(BudgetState {
+                available: total,
+                abandoned: 0,
+            }),
+            released: Condvar::new(),
+        })
+    }
+
+    // An image bigger than the whole budget waits until it can run alone.
+    // Whether it may be decoded at all is up to the per-image limits.
+    pub(super) fn reserve(self: &Arc<Self>, bytes: u64) -> Result<Reservation, Error> {
+        let bytes = bytes.min(self.total);
+        let mut state = self.state.lock().unwrap();
+        while state.available < bytes {
+            if self.total - state.abandoned < bytes {
+                return Err(Error::new(
+                    ErrorKind::OutOfMemory,
+                    format!(
+                        "Not enough memory budget left, {} MiB are held by files that timed out",
+                        state.abandoned.div_ceil(MIB)
+                    ),
+                ));
+            }
+            state = self.released.wait(state).unwrap();
+        }
+        state.available -= bytes;
+        Ok(Reservation {
+            budget: self.clone(),
+            bytes,
+            abandoned: AtomicBool::new(false),
+        })
+    }
+}
+
+// Returns its bytes to the budget when dropped, which may be on another thread
+pub(super) struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+    abandoned: AtomicBool,
+}
+
+impl Reservation {
+    // Marks the bytes as held by a file that timed out, so files needing
+    // them stop waiting
+    pub(super) fn abandon(&self) {
+        if !self.abandoned.swap(true, Ordering::Relaxed) {
+            self.budget.state.lock().unwrap().abandoned += self.bytes;
+            self.budget.released.notify_all();
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut state = self.budget.state.lock().unwrap();
+        state.available += self.bytes;
+        if *self.abandoned.get_mut() {
+            state.abandoned -= self.bytes;
+        }
+        self.budget.released.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::super::Adjustments;
+    use super::*;
+
+    #[test]
+    fn estimates_count_transform_copies() {
+        let dir = temp_dir("peak_bytes");
+        let input = write_png(&dir, "photo.png", 10, 10);
+        assert_eq!(estimate_peak_bytes(&input, &[]), 10 * 10 * 4);
+        let adjust = Transform::Adjust(Adjustments::default());
+        assert_eq!(estimate_peak_bytes(&input, &[adjust]), 10 * 10 * (4 + 16));
+        assert_eq!(estimate_peak_bytes(&dir.join("missing.png"), &[]), 0);
+    }
+
+    #[test]
+    fn abandoned_memory_is_not_waited_for() {
+        let budget = MemoryBudget::new(100);
+        let stuck = budget.reserve(80).unwrap();
+        stuck.abandon();
+
+        // Fits next to the stuck file
+        drop(budget.reserve(20).unwrap());
+        let error = budget.reserve(50).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+
+        // The stuck thread ended after all
+        drop(stuck);
+        drop(budget.reserve(100).unwrap());
+    }
+
+    #[test]
+    fn waits_for_running_files() {
+        let budget = MemoryBudget::new(100);
+        let running = budget.reserve(80).unwrap();
+        let waiting = {
+            let budget = budget.clone();
+            std::thread::spawn(move || budget.reserve(50).map(|r| r.bytes))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!waiting.is_finished());
+        drop(running);
+        assert_eq!(waiting.join().unwrap().unwrap(), 50);
+    }
+}
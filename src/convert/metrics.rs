@@ -0,0 +1,183 @@
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+
+const SSIM_WINDOW: u32 = 8;
+const SSIM_STEP: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityMetrics {
+    // Over the color channels and alpha, infinite when nothing changed
+    pub psnr: f64,
+    // Luma only, 1.0 for identical images
+    pub ssim: f64,
+    // Largest difference of any single channel
+    pub max_delta: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityThreshold {
+    pub min_psnr: f64,
+    pub min_ssim: f64,
+}
+
+impl Default for QualityThreshold {
+    fn default() -> Self {
+        QualityThreshold {
+            min_psnr: 35.0,
+            min_ssim: 0.95,
+        }
+    }
+}
+
+impl QualityMetrics {
+    pub fn is_below(&self, threshold: &QualityThreshold) -> bool {
+        self.psnr < threshold.min_psnr || self.ssim < threshold.min_ssim
+    }
+}
+
+// Compares the encoded result against the image that went into the encoder.
+// The reference is resized when the candidate was downscaled.
+pub fn compare(reference: &DynamicImage, candidate: &DynamicImage) -> QualityMetrics {
+    let same_size =
+        reference.width() == candidate.width() && reference.height() == candidate.height();
+    let resized;
+    let reference = if same_size {
+        reference
+    } else {
+        resized =
+            reference.resize_exact(candidate.width(), candidate.height(), FilterType::Triangle);
+        &resized
+    };
+
+    // Alpha only counts when either side has it, an opaque channel would
+    // dilute the error
+    let (reference_samples, candidate_samples) =
+        if reference.color().has_alpha() || candidate.color().has_alpha() {
+            (
+                reference.to_rgba8().into_raw(),
+                candidate.to_rgba8().into_raw(),
+            )
+        } else {
+            (
+                reference.to_rgb8().into_raw(),
+                candidate.to_rgb8().into_raw(),
+            )
+        };
+    let mut squared_error = 0.0;
+    let mut max_delta = 0;
+    for (a, b) in reference_samples.iter().zip(&candidate_samples) {
+        let delta = a.abs_diff(*b);
+        max_delta = max_delta.max(delta);
+        squared_error += (delta as f64) * (delta as f64);
+    }
+    let mse = squared_error / reference_samples.len().max(1) as f64;
+    let psnr = if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    };
+
+    QualityMetrics {
+        psnr,
+        ssim: luma_ssim(&reference.to_luma8(), &candidate.to_luma8()),
+        max_delta,
+    }
+}
+
+fn luma_ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = a.dimensions();
+    let window_width = SSIM_WINDOW.min(width);
+    let window_height = SSIM_WINDOW.min(height);
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    let mut y = 0;
+    while y + window_height <= height {
+        let mut x = 0;
+        while x + window_width <= width {
+            let n = (window_width * window_height) as f64;
+            let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+                (0.0, 0.0, 0.0, 0.0, 0.0);
+            for wy in y..y + window_height {
+                for wx in x..x + window_width {
+                    let va = a.get_pixel(wx, wy)[0] as f64;
+                    let vb = b.get_pixel(wx, wy)[0] as f64;
+                    sum_a += va;
+                    sum_b += vb;
+                    sum_aa += va * va;
+                    sum_bb += vb * vb;
+                    sum_ab += va * vb;
+                }
+            }
+
+            let mean_a = sum_a / n;
+            let mean_b = sum_b / n;
+            let var_a = sum_aa / n - mean_a * mean_a;
+            let var_b = sum_bb / n - mean_b * mean_b;
+            let covariance = sum_ab / n - mean_a * mean_b;
+
+            total += ((2.0 * mean_a * mean_b + C1) * (2.0 * covariance + C2))
+                / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2));
+            windows += 1;
+            x += SSIM_STEP;
+        }
+        y += SSIM_STEP;
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::gradient;
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn identical_images_have_infinite_psnr() {
+        let img = DynamicImage::ImageRgba8(gradient(32, 32));
+        let metrics = compare(&img, &img);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1e-9);
+        assert_eq!(metrics.max_delta, 0);
+        assert!(!metrics.is_below(&QualityThreshold::default()));
+    }
+
+    #[test]
+    fn known_error_gives_known_psnr() {
+        let flat = |red| {
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([red, 120, 150, 255])))
+        };
+        // One channel of four off by 10, so the mean squared error is 25
+        let metrics = compare(&flat(100), &flat(110));
+        let expected = 10.0 * (255.0f64 * 255.0 / 25.0).log10();
+        assert!((metrics.psnr - expected).abs() < 1e-9);
+        assert_eq!(metrics.max_delta, 10);
+    }
+
+    #[test]
+    fn downscaled_candidates_are_compared_at_their_size() {
+        let flat = |size| {
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(size, size, Rgba([90, 120, 150, 255])))
+        };
+        let metrics = compare(&flat(64), &flat(16));
+        assert_eq!(metrics.psnr, f64::INFINITY);
+    }
+
+    #[test]
+    fn large_differences_fall_below_the_threshold() {
+        let black = DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 255])));
+        let white =
+            DynamicImage::ImageRgba8(RgbaImage::from_pixel(16, 16, Rgba([255, 255, 255, 255])));
+        let metrics = compare(&black, &white);
+        assert_eq!(metrics.max_delta, 255);
+        assert!(metrics.is_below(&QualityThreshold::default()));
+    }
+}
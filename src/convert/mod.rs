@@ -1,17 +1,94 @@
-use image::{ImageFormat, ImageReader};
-use std::io::Error;
-use std::path::PathBuf;
+mod adjust;
+mod auto;
+mod canvas;
+mod color;
+mod converter;
+mod dedupe;
+mod encode;
+mod events;
+mod filter;
+mod inspect;
+mod limits;
+mod metrics;
+mod quantize;
+mod report;
+mod responsive;
+mod rules;
+mod scan;
+#[cfg(test)]
+mod testing;
+mod transform;
+mod watchdog;
+mod watermark;
 
-#[derive(Debug, Clone)]
+use image::{DynamicImage, ImageError, ImageFormat, ImageReader};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Cursor, Error, ErrorKind, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use events::emit;
+use limits::{estimate_peak_bytes, MemoryBudget, Reservation};
+use transform::watermarks;
+use watchdog::{run_with_timeout, write_output};
+use watermark::hold_sources;
+
+pub use adjust::Adjustments;
+pub use auto::{convert_batch_auto, convert_image_auto, AutoFormat};
+pub use canvas::{Alignment, Canvas};
+pub use color::OutputColorType;
+pub use converter::{Collision, Converter, ConverterBuilder};
+pub use dedupe::{
+    find_duplicates, find_duplicates_with_thumbnails, DuplicateGroup, ImageHashes,
+    DEFAULT_MAX_DISTANCE,
+};
+pub use encode::{EncoderSettings, SizeTarget};
+pub use events::ProgressEvent;
+pub use filter::UnsharpMask;
+pub use inspect::{inspect, ImageInfo};
+pub use limits::DecodeLimits;
+pub use metrics::{QualityMetrics, QualityThreshold};
+pub use quantize::{Dither, QuantizeOptions};
+pub use report::{error_kind, FileRecord};
+pub use responsive::{convert_responsive_batch, ResponsiveSet};
+pub use rules::{
+    convert_batch_rules, convert_image_rules, preview_rules, FormatRule, RuleCondition,
+};
+pub use scan::{scan_file, scan_files, ScanProblem, ScanResult};
+pub use transform::{Resize, Transform};
+pub use watermark::{Anchor, Watermark, WatermarkSource};
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConvertFormat {
     Jpeg,
     Png,
     Webp,
     Bmp,
     Gif,
+    Avif,
 }
 
 impl ConvertFormat {
+    pub const ALL: [ConvertFormat; 6] = [
+        ConvertFormat::Jpeg,
+        ConvertFormat::Png,
+        ConvertFormat::Webp,
+        ConvertFormat::Bmp,
+        ConvertFormat::Gif,
+        ConvertFormat::Avif,
+    ];
+
+    // Accepts a name or extension in any case, e.g. "jpg", "JPEG" or "webp"
+    pub fn from_name(name: &str) -> Option<ConvertFormat> {
+        let name = name.to_ascii_lowercase();
+        ConvertFormat::ALL
+            .into_iter()
+            .find(|format| format.extension() == name || format.name().to_ascii_lowercase() == name)
+    }
+
     pub fn extension(&self) -> &str {
         match self {
             ConvertFormat::Jpeg => "jpg",
@@ -19,6 +96,45 @@ impl ConvertFormat {
             ConvertFormat::Webp => "webp",
             ConvertFormat::Bmp => "bmp",
             ConvertFormat::Gif => "gif",
+            ConvertFormat::Avif => "avif",
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ConvertFormat::Jpeg => "JPEG",
+            ConvertFormat::Png => "PNG",
+            ConvertFormat::Webp => "WebP",
+            ConvertFormat::Bmp => "BMP",
+            ConvertFormat::Gif => "GIF",
+            ConvertFormat::Avif => "AVIF",
+        }
+    }
+
+    // Formats with a lossy quality knob, which a size target can search over
+    pub fn supports_quality(&self) -> bool {
+        matches!(
+            self,
+            ConvertFormat::Jpeg | ConvertFormat::Webp | ConvertFormat::Avif
+        )
+    }
+
+    // Whether this build can decode the format again, which scoring and
+    // verifying outputs need. The avif feature only brings the encoder.
+    pub fn can_decode(&self) -> bool {
+        !matches!(self, ConvertFormat::Avif)
+    }
+
+    pub fn supports_color_type(&self, color_type: OutputColorType) -> bool {
+        match self {
+            ConvertFormat::Png => true,
+            // Only the indexed PNG writer stores 1 bit per pixel, the other
+            // encoders would write 8-bit gray
+            _ if color_type == OutputColorType::Bit1 => false,
+            ConvertFormat::Jpeg => !color_type.has_alpha() && !color_type.is_16_bit(),
+            ConvertFormat::Webp | ConvertFormat::Bmp | ConvertFormat::Gif | ConvertFormat::Avif => {
+                !color_type.is_16_bit()
+            }
         }
     }
 
@@ -29,124 +145,978 @@ impl ConvertFormat {
             ConvertFormat::Webp => ImageFormat::WebP,
             ConvertFormat::Bmp => ImageFormat::Bmp,
             ConvertFormat::Gif => ImageFormat::Gif,
+            ConvertFormat::Avif => ImageFormat::Avif,
+        }
+    }
+}
+
+// One encoded output, each with its own encoder settings
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    pub format: ConvertFormat,
+    pub encoder: EncoderSettings,
+}
+
+impl OutputTarget {
+    pub fn new(format: ConvertFormat) -> Self {
+        OutputTarget {
+            format,
+            encoder: EncoderSettings::default(),
         }
     }
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    pub transforms: Vec<Transform>,
+    pub quantize: QuantizeOptions,
+    // Keep the decoded color type when unset
+    pub color_type: Option<OutputColorType>,
+    pub color_dither: Dither,
+    // Encoder settings for single-format conversions
+    pub encoder: EncoderSettings,
+    // Search encoder quality until the output fits this budget
+    pub size_target: Option<SizeTarget>,
+    // Candidates tried when the output format is picked automatically
+    pub auto: AutoFormat,
+    // Decode every output again and measure the loss, flagging files below this
+    pub verify: Option<QualityThreshold>,
+    // Rename converted inputs whose extension doesn't match their content
+    pub fix_extension: bool,
+    pub limits: DecodeLimits,
+    // Batch files taking longer fail as timed out and keep no output
+    pub timeout: Option<Duration>,
+    // Receives every batch's progress as it happens
+    pub events: Option<Sender<ProgressEvent>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvertStats {
+    pub format: ConvertFormat,
+    pub width: u32,
+    pub height: u32,
+    pub output_bytes: u64,
+    // Encoder quality used, including the one picked by a size target search
+    pub quality: Option<u8>,
+    // Loss against the encoder input, when the output was decoded and checked
+    pub metrics: Option<QualityMetrics>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    // Outputs written
+    pub success_count: usize,
+    // Outputs that failed, an input that failed as a whole counts once
+    pub error_count: usize,
+    pub errors: Vec<String>,
+    pub converted: Vec<ConvertedFile>,
+    // One per output written and per failure, for exporting
+    pub records: Vec<FileRecord>,
+    // Inputs left alone because every output already existed
+    pub skipped: Vec<PathBuf>,
+}
+
+// Every output written for one input, in target order
+#[derive(Debug, Clone)]
+pub struct ConvertedFile {
+    pub input: PathBuf,
+    pub outputs: Vec<(PathBuf, ConvertStats)>,
+    // Description of the format rule that picked the outputs
+    pub rule: Option<String>,
+    pub extension_mismatch: Option<ExtensionMismatch>,
+}
+
+// An input whose extension names a different format than its content, e.g. a
+// PNG saved as photo.jpg
+#[derive(Debug, Clone)]
+pub struct ExtensionMismatch {
+    // Lowercased, empty when the file has none
+    pub extension: String,
+    pub detected: ImageFormat,
+    // Where the input was moved when its extension was fixed
+    pub renamed_to: Option<PathBuf>,
+}
+
+impl ExtensionMismatch {
+    pub fn correct_extension(&self) -> &'static str {
+        self.detected
+            .extensions_str()
+            .first()
+            .copied()
+            .unwrap_or("img")
+    }
+
+    pub fn describe(&self) -> String {
+        let named = if self.extension.is_empty() {
+            "has no extension".to_string()
+        } else {
+            format!("is named .{}", self.extension)
+        };
+        match &self.renamed_to {
+            Some(path) => format!(
+                "{} but contains {}, renamed to {}",
+                named,
+                self.correct_extension(),
+                path.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            None => format!("{} but contains {}", named, self.correct_extension()),
+        }
+    }
+}
+
+// Compares the extension with the format sniffed from the first bytes
+pub fn extension_mismatch(input_path: &Path) -> Option<ExtensionMismatch> {
+    let detected = ImageReader::open(input_path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .format()?;
+    if ImageFormat::from_path(input_path).ok() == Some(detected) {
+        return None;
+    }
+    Some(ExtensionMismatch {
+        extension: input_path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default(),
+        detected,
+        renamed_to: None,
+    })
+}
+
+// Renames the input to the extension of its content. Refuses to replace an
+// existing file, which may be an output written next to it.
+pub fn fix_extension(input_path: &Path, mismatch: &mut ExtensionMismatch) -> Result<(), Error> {
+    let fixed = input_path.with_extension(mismatch.correct_extension());
+    if fixed.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("{} already exists", fixed.display()),
+        ));
+    }
+    std::fs::rename(input_path, &fixed)?;
+    mismatch.renamed_to = Some(fixed);
+    Ok(())
+}
+
+// Run once an input has been converted, so a rename can't affect its outputs
+fn check_extension(input_path: &Path, options: &ConvertOptions) -> Option<ExtensionMismatch> {
+    let mut mismatch = extension_mismatch(input_path)?;
+    if options.fix_extension && !watchdog::timed_out() {
+        if let Err(e) = fix_extension(input_path, &mut mismatch) {
+            eprintln!("Could not fix extension of {}: {}", input_path.display(), e);
+        }
+    }
+    Some(mismatch)
+}
+
 pub fn anything_to_jpg(path: PathBuf, output_path: PathBuf) -> Result<(), Error> {
-    convert_image(path, output_path, ConvertFormat::Jpeg)
+    convert_image(
+        path,
+        output_path,
+        ConvertFormat::Jpeg,
+        &ConvertOptions::default(),
+    )
+    .map(|_| ())
 }
 
 pub fn convert_image(
     input_path: PathBuf,
     output_path: PathBuf,
     format: ConvertFormat,
-) -> Result<(), Error> {
-    let img = ImageReader::open(&input_path)?.decode();
-    match img {
-        Ok(img) => {
-            let result = img.save_with_format(&output_path, format.image_format());
-            match result {
-                Ok(_) => {
-                    println!(
-                        "Successfully converted {} to {}",
-                        input_path.display(),
-                        output_path.display()
-                    );
-                }
-                Err(e) => {
-                    eprintln!("Failed to save image: {}", e);
-                    return Err(Error::new(
-                        std::io::ErrorKind::Other,
-                        format!("Save failed: {}", e),
-                    ));
-                }
-            }
+    options: &ConvertOptions,
+) -> Result<ConvertStats, Error> {
+    let target = OutputTarget {
+        format,
+        encoder: options.encoder.clone(),
+    };
+    let mut results = convert_image_targets(input_path, &[(output_path, target)], options)?;
+    results.remove(0)
+}
+
+// Decode and transform once, then encode into every target. The outer error
+// covers decoding, the inner results are per target.
+pub fn convert_image_targets(
+    input_path: PathBuf,
+    outputs: &[(PathBuf, OutputTarget)],
+    options: &ConvertOptions,
+) -> Result<Vec<Result<ConvertStats, Error>>, Error> {
+    let img = load_image(&input_path, options)?;
+
+    let results = outputs
+        .iter()
+        .map(|(output_path, target)| {
+            let stats = encode_to_file(&img, output_path, target, options)?;
+            println!(
+                "Successfully converted {} to {}",
+                input_path.display(),
+                output_path.display()
+            );
+            Ok(stats)
+        })
+        .collect();
+
+    Ok(results)
+}
+
+// Same pipeline as `convert_image`, for images held in memory
+pub fn convert_bytes(
+    input: &[u8],
+    format: ConvertFormat,
+    options: &ConvertOptions,
+) -> Result<Vec<u8>, Error> {
+    let mut output = Vec::new();
+    convert_stream(Cursor::new(input), &mut output, format, options)?;
+    Ok(output)
+}
+
+// The format is detected from the content, `output` receives the encoded image
+// in one write once encoding has succeeded
+pub fn convert_stream(
+    input: impl Read + Seek,
+    mut output: impl Write,
+    format: ConvertFormat,
+    options: &ConvertOptions,
+) -> Result<ConvertStats, Error> {
+    let reader = ImageReader::new(BufReader::new(input)).with_guessed_format()?;
+    let (img, _) = decode_reader(reader, &options.limits, "stream")?;
+    let img = prepare_image(img, options)?;
+    let target = OutputTarget {
+        format,
+        encoder: options.encoder.clone(),
+    };
+    encode_with(&img, &target, options, |bytes| {
+        output.write_all(bytes)?;
+        output.flush()
+    })
+}
+
+// Decodes any supported image within `limits`, the format is detected from
+// the content
+pub fn open_image(input_path: &Path, limits: &DecodeLimits) -> Result<DynamicImage, Error> {
+    decode_image(input_path, limits).map(|(img, _)| img)
+}
+
+// Decoded, transformed and converted to the requested color type, ready to encode
+fn load_image(input_path: &Path, options: &ConvertOptions) -> Result<DynamicImage, Error> {
+    let (img, _) = decode_image(input_path, &options.limits)?;
+    prepare_image(img, options)
+}
+
+// The decoded image along with the format it was read as. The decoder is
+// picked from the file's first bytes, the extension is only a fallback.
+fn decode_image(
+    input_path: &Path,
+    limits: &DecodeLimits,
+) -> Result<(DynamicImage, Option<ImageFormat>), Error> {
+    let reader = ImageReader::open(input_path)?.with_guessed_format()?;
+    decode_reader(reader, limits, input_path.display())
+}
+
+fn decode_reader<R: BufRead + Seek>(
+    mut reader: ImageReader<R>,
+    limits: &DecodeLimits,
+    source: impl Display,
+) -> Result<(DynamicImage, Option<ImageFormat>), Error> {
+    reader.limits(limits.image_limits());
+    let format = reader.format();
+    match reader.decode() {
+        Ok(img) => Ok((img, format)),
+        Err(ImageError::Limits(e)) => {
+            eprintln!("Refused to decode {}: {}", source, e);
+            Err(Error::new(
+                ErrorKind::OutOfMemory,
+                format!("Image exceeds the decode limits: {}", e),
+            ))
         }
         Err(e) => {
             eprintln!("Failed to convert image: {}", e);
-            return Err(Error::new(
-                std::io::ErrorKind::Other,
+            Err(Error::new(
+                ErrorKind::InvalidData,
                 format!("Conversion failed: {}", e),
-            ));
+            ))
         }
     }
+}
 
-    Ok(())
+fn prepare_image(img: DynamicImage, options: &ConvertOptions) -> Result<DynamicImage, Error> {
+    let img = transform::apply_transforms(img, &options.transforms, &options.limits)?;
+    Ok(match options.color_type {
+        Some(color_type) => color::convert_color(&img, color_type, options.color_dither),
+        None => img,
+    })
 }
 
-pub fn convert_batch_parallel(
+fn encode_to_file(
+    img: &DynamicImage,
+    output_path: &Path,
+    target: &OutputTarget,
+    options: &ConvertOptions,
+) -> Result<ConvertStats, Error> {
+    encode_with(img, target, options, |bytes| {
+        write_output(output_path, bytes)
+    })
+}
+
+// Encodes and hands the finished bytes to `write`
+fn encode_with(
+    img: &DynamicImage,
+    target: &OutputTarget,
+    options: &ConvertOptions,
+    write: impl FnOnce(&[u8]) -> Result<(), Error>,
+) -> Result<ConvertStats, Error> {
+    check_color_type(&target.format, options)?;
+
+    let result = encode::encode_image(img, target, options)
+        .and_then(|encoded| write(&encoded.bytes).map(|_| encoded));
+    match result {
+        Ok(encoded) => Ok(ConvertStats {
+            format: target.format.clone(),
+            width: encoded.width,
+            height: encoded.height,
+            output_bytes: encoded.bytes.len() as u64,
+            quality: encoded.quality,
+            metrics: options
+                .verify
+                .as_ref()
+                .and_then(|_| measure(img, &target.format, &encoded.bytes)),
+        }),
+        Err(e) => {
+            eprintln!("Failed to save image: {}", e);
+            Err(Error::new(e.kind(), format!("Save failed: {}", e)))
+        }
+    }
+}
+
+// None when the output cannot be decoded again, see `ConvertFormat::can_decode`
+fn measure(img: &DynamicImage, format: &ConvertFormat, bytes: &[u8]) -> Option<QualityMetrics> {
+    match image::load_from_memory_with_format(bytes, format.image_format()) {
+        Ok(decoded) => Some(metrics::compare(img, &decoded)),
+        Err(e) => {
+            eprintln!("Could not verify {} output: {}", format.name(), e);
+            None
+        }
+    }
+}
+
+// A conversion that panicked, kept as its own type so reports can tell it
+// apart from ordinary failures
+#[derive(Debug)]
+pub struct ConversionPanic(pub String);
+
+impl std::fmt::Display for ConversionPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Panicked: {}", self.0)
+    }
+}
+
+impl std::error::Error for ConversionPanic {}
+
+// Turns a panic in `f` into an error, so one broken file can't take down the
+// rest of a batch or the GUI running it
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, Error>) -> Result<T, Error> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(Error::other(ConversionPanic(message)))
+    })
+}
+
+fn check_color_type(format: &ConvertFormat, options: &ConvertOptions) -> Result<(), Error> {
+    match options.color_type {
+        Some(color_type) if !format.supports_color_type(color_type) => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "{} output cannot store {} pixels",
+                format.name(),
+                color_type.name()
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Runs `convert` over every file in parallel, collecting what each one wrote
+// `convert` owns what it uses, a file that times out leaves it running
+fn run_batch(
     files: Vec<PathBuf>,
+    options: &ConvertOptions,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    convert: impl Fn(&PathBuf) -> Result<ConvertedFile, Error> + Send + Sync + 'static,
+) -> BatchSummary {
+    run_batch_outputs(
+        files,
+        options,
+        Vec::new(),
+        progress_callback,
+        move |file_path| Ok((convert(file_path)?, Vec::new())),
+    )
+}
+
+// One output of an input that failed while others were written
+struct FailedOutput {
+    path: PathBuf,
     format: ConvertFormat,
-    overwrite: bool,
+    error: Error,
+}
+
+// `run_batch` for conversions that can fail per output. `skipped` files were
+// left out before the batch and are only reported.
+fn run_batch_outputs(
+    files: Vec<PathBuf>,
+    options: &ConvertOptions,
+    skipped: Vec<PathBuf>,
     progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
-) -> (usize, usize, Vec<String>) {
-    use rayon::prelude::*;
-    use std::sync::{Arc, Mutex};
+    convert: impl Fn(&PathBuf) -> Result<(ConvertedFile, Vec<FailedOutput>), Error>
+        + Send
+        + Sync
+        + 'static,
+) -> BatchSummary {
+    // Logos and fonts are loaded once, one that can't be fails the batch up front
+    let mut sources = hold_sources(watermarks(&options.transforms), &options.limits);
+    if let Some(e) = sources.error.take() {
+        return fail_batch(files, options, skipped, progress_callback, e);
+    }
 
-    let total_files = files.len();
-    let success_count = Arc::new(Mutex::new(0));
-    let error_count = Arc::new(Mutex::new(0));
-    let errors = Arc::new(Mutex::new(Vec::new()));
-    let processed_count = Arc::new(Mutex::new(0));
-
-    // Process files in parallel using rayon
-    files.par_iter().for_each(|file_path| {
-        let input_stem = file_path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .unwrap_or("converted");
-
-        let output_filename = if overwrite {
-            format!("{}.{}", input_stem, format.extension())
-        } else {
-            let mut counter = 1;
-            let base_path = file_path.parent().unwrap_or(std::path::Path::new("."));
-            loop {
-                let filename = format!(
-                    "{}_converted_{}.{}",
-                    input_stem,
-                    counter,
-                    format.extension()
-                );
-                let test_path = base_path.join(&filename);
-                if !test_path.exists() {
-                    break filename;
+    let convert = Arc::new(convert);
+    let convert_file = |file_path: &PathBuf, reservation: Result<Reservation, Error>| {
+        emit(options, || ProgressEvent::Started {
+            file: file_path.clone(),
+        });
+        let started = Instant::now();
+        let result = reservation.and_then(|reservation| {
+            let reservation = Arc::new(reservation);
+            let held = reservation.clone();
+            let convert = convert.clone();
+            let input = file_path.clone();
+            // A file that times out keeps its memory until its thread is done
+            let result = run_with_timeout(options.timeout, move || {
+                let _reservation = held;
+                catch_panic(|| convert(&input))
+            });
+            if result
+                .as_ref()
+                .is_err_and(|e| e.kind() == ErrorKind::TimedOut)
+            {
+                reservation.abandon();
+            }
+            result
+        });
+        match &result {
+            Ok((converted, failed)) => {
+                for (output, stats) in &converted.outputs {
+                    emit(options, || ProgressEvent::Finished {
+                        file: file_path.clone(),
+                        output: output.clone(),
+                        stats: stats.clone(),
+                    });
+                }
+                for failure in failed {
+                    emit(options, || ProgressEvent::Failed {
+                        file: file_path.clone(),
+                        output: Some(failure.path.clone()),
+                        error_kind: error_kind(&failure.error),
+                        error: failure.error.to_string(),
+                    });
                 }
-                counter += 1;
             }
-        };
+            Err(e) => emit(options, || ProgressEvent::Failed {
+                file: file_path.clone(),
+                output: None,
+                error_kind: error_kind(e),
+                error: e.to_string(),
+            }),
+        }
+        (result, started.elapsed())
+    };
+    let run = || {
+        map_files(
+            &files,
+            &options.limits,
+            &options.transforms,
+            progress_callback,
+            convert_file,
+        )
+    };
 
-        let output_path = file_path
-            .parent()
-            .unwrap_or(std::path::Path::new("."))
-            .join(output_filename);
+    // Waiting on a file under a time limit blocks the worker. The waits get a
+    // pool of their own so encoders that split their work over the global
+    // pool, like AVIF, still find free workers there.
+    let pool = options.timeout.and_then(|_| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(rayon::current_num_threads())
+            .build()
+            .ok()
+    });
+    let results = match pool {
+        Some(pool) => pool.install(run),
+        None => run(),
+    };
 
-        match convert_image(file_path.clone(), output_path.clone(), format.clone()) {
-            Ok(_) => {
-                *success_count.lock().unwrap() += 1;
-                println!("Successfully converted: {}", file_path.display());
+    let mut summary = BatchSummary {
+        skipped,
+        ..BatchSummary::default()
+    };
+    for (file_path, (result, duration)) in files.iter().zip(results) {
+        let file_name = file_path.file_name().unwrap_or_default().to_string_lossy();
+        match result {
+            Ok((converted, failed)) => {
+                summary.success_count += converted.outputs.len();
+                summary
+                    .records
+                    .extend(FileRecord::converted(&converted, duration));
+                for failure in failed {
+                    summary.error_count += 1;
+                    summary.errors.push(format!(
+                        "{} ({}): {}",
+                        file_name,
+                        failure.format.name(),
+                        failure.error
+                    ));
+                    summary.records.push(FileRecord::failed(
+                        file_path,
+                        Some(failure.path),
+                        &failure.error,
+                        duration,
+                    ));
+                }
+                if !converted.outputs.is_empty() {
+                    summary.converted.push(converted);
+                }
             }
             Err(e) => {
-                *error_count.lock().unwrap() += 1;
-                let error_msg = format!(
-                    "{}: {}",
-                    file_path.file_name().unwrap_or_default().to_string_lossy(),
-                    e
-                );
-                errors.lock().unwrap().push(error_msg);
+                summary.error_count += 1;
+                summary.errors.push(format!("{}: {}", file_name, e));
+                summary
+                    .records
+                    .push(FileRecord::failed(file_path, None, &e, duration));
             }
         }
+    }
+    emit(options, || ProgressEvent::BatchDone {
+        summary: summary.clone(),
+    });
+    summary
+}
+
+// Fails every file of a batch with the same `error`, reported once
+fn fail_batch(
+    files: Vec<PathBuf>,
+    options: &ConvertOptions,
+    skipped: Vec<PathBuf>,
+    progress_callback: impl Fn(usize, usize),
+    error: Error,
+) -> BatchSummary {
+    let records = files
+        .iter()
+        .map(|file_path| {
+            emit(options, || ProgressEvent::Failed {
+                file: file_path.clone(),
+                output: None,
+                error_kind: error_kind(&error),
+                error: error.to_string(),
+            });
+            FileRecord::failed(file_path, None, &error, Duration::ZERO)
+        })
+        .collect();
+    progress_callback(files.len(), files.len());
 
-        // Update progress
-        let mut processed = processed_count.lock().unwrap();
-        *processed += 1;
-        progress_callback(*processed, total_files);
+    let summary = BatchSummary {
+        error_count: files.len(),
+        errors: vec![error.to_string()],
+        records,
+        skipped,
+        ..BatchSummary::default()
+    };
+    emit(options, || ProgressEvent::BatchDone {
+        summary: summary.clone(),
     });
+    summary
+}
+
+// Calls `f` for every file in parallel, reporting progress as each finishes.
+// Files wait for room in the memory budget before they are decoded, along
+// with what `transforms` will need. `f` gets the file's share, or why there
+// is none, and hands it back by dropping it. Results come back in file order.
+fn map_files<T: Send>(
+    files: &[PathBuf],
+    limits: &DecodeLimits,
+    transforms: &[Transform],
+    progress_callback: impl Fn(usize, usize) + Send + Sync,
+    f: impl Fn(&PathBuf, Result<Reservation, Error>) -> T + Send + Sync,
+) -> Vec<T> {
+    use rayon::prelude::*;
+
+    let total_files = files.len();
+    let processed_count = Mutex::new(0);
+    let budget = MemoryBudget::new(limits.memory_budget);
+
+    files
+        .par_iter()
+        .map(|file_path| {
+            let result = f(
+                file_path,
+                budget.reserve(estimate_peak_bytes(file_path, transforms)),
+            );
+
+            let mut processed = processed_count.lock().unwrap();
+            *processed += 1;
+            progress_callback(*processed, total_files);
+            result
+        })
+        .collect()
+}
+
+// Next to the input, either replacing a same-named file or numbered to avoid it
+pub fn output_path_for(input_path: &Path, format: &ConvertFormat, overwrite: bool) -> PathBuf {
+    let base_path = input_path.parent().unwrap_or(Path::new("."));
+    output_path_in(base_path, input_path, format, overwrite, &HashSet::new())
+}
+
+// Names in `taken` are treated like existing files, except that overwriting
+// them falls back to numbering
+fn output_path_in(
+    base_path: &Path,
+    input_path: &Path,
+    format: &ConvertFormat,
+    overwrite: bool,
+    taken: &HashSet<PathBuf>,
+) -> PathBuf {
+    let input_stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("converted");
+
+    if overwrite {
+        let path = base_path.join(format!("{}.{}", input_stem, format.extension()));
+        if !taken.contains(&path) {
+            return path;
+        }
+    }
+
+    let mut counter = 1;
+    loop {
+        let path = base_path.join(format!(
+            "{}_converted_{}.{}",
+            input_stem,
+            counter,
+            format.extension()
+        ));
+        if !path.exists() && !taken.contains(&path) {
+            return path;
+        }
+        counter += 1;
+    }
+}
+
+// Output names given out so far in one batch. Inputs sharing a stem, like
+// photo.png and photo.tif, would otherwise be given the same name and
+// overwrite each other's output.
+#[derive(Default)]
+struct ClaimedNames(Mutex<HashSet<PathBuf>>);
+
+impl ClaimedNames {
+    fn claim(
+        &self,
+        base_path: &Path,
+        input_path: &Path,
+        format: &ConvertFormat,
+        overwrite: bool,
+    ) -> PathBuf {
+        let mut claimed = self.0.lock().unwrap();
+        let path = output_path_in(base_path, input_path, format, overwrite, &claimed);
+        claimed.insert(path.clone());
+        path
+    }
+
+    // `output_path_for` within the batch
+    fn claim_next_to(&self, input_path: &Path, format: &ConvertFormat, overwrite: bool) -> PathBuf {
+        let base_path = input_path.parent().unwrap_or(Path::new("."));
+        self.claim(base_path, input_path, format, overwrite)
+    }
+
+    // Claims files that belong together in one go, so they all share a name.
+    // `names(None)` are the plain names, kept when none of them exist or when
+    // overwriting, `names(Some(n))` the n-th numbered alternative.
+    fn claim_group(
+        &self,
+        overwrite: bool,
+        names: impl Fn(Option<u32>) -> Vec<PathBuf>,
+    ) -> Vec<PathBuf> {
+        let mut claimed = self.0.lock().unwrap();
+        let free = |paths: &[PathBuf], may_exist: bool| {
+            paths
+                .iter()
+                .all(|path| !claimed.contains(path) && (may_exist || !path.exists()))
+        };
+        let paths = std::iter::once(None)
+            .chain((1..).map(Some))
+            .map(&names)
+            .enumerate()
+            .find(|(attempt, paths)| free(paths, overwrite && *attempt == 0))
+            .map(|(_, paths)| paths)
+            .unwrap_or_default();
+        claimed.extend(paths.iter().cloned());
+        paths
+    }
+}
+
+pub fn convert_batch_parallel(
+    files: Vec<PathBuf>,
+    targets: &[OutputTarget],
+    options: &ConvertOptions,
+    overwrite: bool,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+) -> BatchSummary {
+    convert_batch_targets(
+        files,
+        targets,
+        options,
+        progress_callback,
+        |input, format, claimed| Some(claimed.claim_next_to(input, format, overwrite)),
+    )
+}
+
+// `output_path` names the file each target of an input is written to, None
+// leaves the target out. Inputs left without targets are skipped. All names
+// are picked before anything is written, each one is claimed for the batch.
+fn convert_batch_targets(
+    files: Vec<PathBuf>,
+    targets: &[OutputTarget],
+    options: &ConvertOptions,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+    output_path: impl Fn(&Path, &ConvertFormat, &ClaimedNames) -> Option<PathBuf>,
+) -> BatchSummary {
+    let claimed = ClaimedNames::default();
+    let mut plans = HashMap::new();
+    let mut pending = Vec::new();
+    let mut skipped = Vec::new();
+    for file_path in files {
+        let outputs: Vec<(PathBuf, OutputTarget)> = targets
+            .iter()
+            .filter_map(|target| {
+                Some((
+                    output_path(&file_path, &target.format, &claimed)?,
+                    target.clone(),
+                ))
+            })
+            .collect();
+        if outputs.is_empty() {
+            emit(options, || ProgressEvent::Skipped {
+                file: file_path.clone(),
+                reason: "Every output already exists".to_string(),
+            });
+            skipped.push(file_path);
+        } else {
+            plans.insert(file_path.clone(), outputs);
+            pending.push(file_path);
+        }
+    }
+
+    let options_clone = options.clone();
+    run_batch_outputs(
+        pending,
+        options,
+        skipped,
+        progress_callback,
+        move |file_path| {
+            let outputs = &plans[file_path];
+            let results = convert_image_targets(file_path.clone(), outputs, &options_clone)?;
+
+            let mut written = Vec::new();
+            let mut failed = Vec::new();
+            for ((output_path, target), result) in outputs.iter().cloned().zip(results) {
+                match result {
+                    Ok(stats) => written.push((output_path, stats)),
+                    Err(error) => failed.push(FailedOutput {
+                        path: output_path,
+                        format: target.format,
+                        error,
+                    }),
+                }
+            }
+            if !written.is_empty() {
+                println!("Successfully converted: {}", file_path.display());
+            }
+
+            let converted = ConvertedFile {
+                input: file_path.clone(),
+                outputs: written,
+                rule: None,
+                extension_mismatch: check_extension(file_path, &options_clone),
+            };
+            Ok((converted, failed))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::{gradient, temp_dir, write_png};
+    use super::*;
 
-    let final_success = *success_count.lock().unwrap();
-    let final_errors = *error_count.lock().unwrap();
-    let error_list = errors.lock().unwrap().clone();
+    fn output_names(summary: &BatchSummary) -> Vec<String> {
+        let mut names: Vec<String> = summary
+            .converted
+            .iter()
+            .flat_map(|c| &c.outputs)
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    // photo.png and photo.jpg in one folder
+    fn same_stem_inputs(name: &str) -> (PathBuf, Vec<PathBuf>) {
+        let dir = temp_dir(name);
+        let png = write_png(&dir, "photo.png", 8, 8);
+        let jpg = dir.join("photo.jpg");
+        DynamicImage::ImageRgba8(gradient(8, 8))
+            .to_rgb8()
+            .save(&jpg)
+            .unwrap();
+        (dir, vec![png, jpg])
+    }
+
+    #[test]
+    fn inputs_sharing_a_stem_get_their_own_outputs() {
+        for overwrite in [false, true] {
+            let (dir, files) = same_stem_inputs(&format!("same_stem_{}", overwrite));
+            let targets = [OutputTarget::new(ConvertFormat::Bmp)];
+            let summary = convert_batch_parallel(
+                files,
+                &targets,
+                &ConvertOptions::default(),
+                overwrite,
+                |_, _| {},
+            );
+
+            assert_eq!(summary.success_count, 2);
+            let names = output_names(&summary);
+            let expected = if overwrite {
+                ["photo.bmp", "photo_converted_1.bmp"]
+            } else {
+                ["photo_converted_1.bmp", "photo_converted_2.bmp"]
+            };
+            assert_eq!(names, expected);
+            for name in names {
+                assert!(dir.join(name).exists());
+            }
+        }
+    }
+
+    #[test]
+    fn rule_and_auto_batches_claim_names_too() {
+        let (_, files) = same_stem_inputs("same_stem_rules");
+        let fallback = OutputTarget::new(ConvertFormat::Bmp);
+        let options = ConvertOptions::default();
+        let summary = convert_batch_rules(files.clone(), &[], &fallback, &options, true, |_, _| {});
+        assert_eq!(
+            output_names(&summary),
+            ["photo.bmp", "photo_converted_1.bmp"]
+        );
+
+        let summary = convert_batch_auto(files, &options, false, |_, _| {});
+        assert_eq!(summary.success_count, 2);
+        let names = output_names(&summary);
+        assert_ne!(names[0], names[1]);
+    }
+
+    #[test]
+    fn bytes_round_trip_through_every_format() {
+        let img = DynamicImage::ImageRgba8(gradient(24, 16));
+        let mut png = Vec::new();
+        img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        let options = ConvertOptions::default();
+
+        for format in ConvertFormat::ALL.iter().filter(|f| f.can_decode()) {
+            let bytes = convert_bytes(&png, format.clone(), &options).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(
+                (decoded.width(), decoded.height()),
+                (24, 16),
+                "{}",
+                format.name()
+            );
+        }
+
+        // Lossless formats give back the same pixels
+        let bytes = convert_bytes(&png, ConvertFormat::Png, &options).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(decoded.to_rgba8(), img.to_rgba8());
+    }
 
-    (final_success, final_errors, error_list)
+    #[test]
+    fn streams_get_nothing_when_decoding_fails() {
+        let mut output = Vec::new();
+        let result = convert_stream(
+            Cursor::new(b"not an image".to_vec()),
+            &mut output,
+            ConvertFormat::Png,
+            &ConvertOptions::default(),
+        );
+        assert!(result.is_err());
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn batches_report_each_file_then_finish() {
+        let dir = temp_dir("batch_events");
+        let good = write_png(&dir, "good.png", 8, 8);
+        let skip = write_png(&dir, "skip.png", 8, 8);
+        let broken = dir.join("broken.png");
+        std::fs::write(&broken, b"not an image").unwrap();
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let options = ConvertOptions {
+            events: Some(sender),
+            ..ConvertOptions::default()
+        };
+        let targets = [OutputTarget::new(ConvertFormat::Bmp)];
+        let summary = convert_batch_targets(
+            vec![good.clone(), skip.clone(), broken.clone()],
+            &targets,
+            &options,
+            |_, _| {},
+            |input, format, claimed| {
+                (input != skip).then(|| claimed.claim_next_to(input, format, false))
+            },
+        );
+        drop(options);
+        let events: Vec<ProgressEvent> = receiver.iter().collect();
+
+        let names: Vec<&str> = events.iter().map(|e| e.name()).collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], "skipped");
+        assert_eq!(names[5], "batch_done");
+
+        // Each file starts before it ends, however the files interleave
+        let position = |name: &str, path: &PathBuf| {
+            events.iter().position(|event| {
+                event.name() == name
+                    && match event {
+                        ProgressEvent::Started { file }
+                        | ProgressEvent::Finished { file, .. }
+                        | ProgressEvent::Failed { file, .. }
+                        | ProgressEvent::Skipped { file, .. } => file == path,
+                        ProgressEvent::BatchDone { .. } => false,
+                    }
+            })
+        };
+        assert_eq!(position("skipped", &skip), Some(0));
+        assert!(position("started", &good).unwrap() < position("finished", &good).unwrap());
+        assert!(position("started", &broken).unwrap() < position("failed", &broken).unwrap());
+        assert_eq!(position("started", &skip), None);
+
+        let Some(ProgressEvent::BatchDone { summary: reported }) = events.last() else {
+            unreachable!()
+        };
+        assert_eq!(reported.success_count, summary.success_count);
+        assert_eq!(reported.error_count, 1);
+        assert_eq!(reported.skipped, [skip]);
+    }
 }
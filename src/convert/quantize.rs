@@ -0,0 +1,465 @@
+use image::RgbaImage;
+use std::collections::HashMap;
+use std::io::{Error, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Dither {
+    None,
+    #[default]
+    FloydSteinberg,
+    Ordered,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuantizeOptions {
+    // Palette size, clamped to 2..=256
+    pub max_colors: u16,
+    pub dither: Dither,
+    // 0.0 disables dithering, 1.0 is full strength
+    pub dither_strength: f32,
+    // Emit palette-based PNGs instead of truecolor ones
+    pub indexed_png: bool,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        QuantizeOptions {
+            max_colors: 256,
+            dither: Dither::FloydSteinberg,
+            dither_strength: 1.0,
+            indexed_png: false,
+        }
+    }
+}
+
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+}
+
+pub(super) const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+pub fn quantize(img: &RgbaImage, options: &QuantizeOptions) -> IndexedImage {
+    let max_colors = options.max_colors.clamp(2, 256) as usize;
+    let palette = build_palette(img, max_colors);
+
+    // An exact palette needs no dithering, every pixel has a perfect match
+    let strength = options.dither_strength.clamp(0.0, 1.0);
+    let dither = if palette.exact || strength == 0.0 {
+        Dither::None
+    } else {
+        options.dither
+    };
+
+    let indices = match dither {
+        Dither::None => map_nearest(img, &palette.colors),
+        Dither::FloydSteinberg => map_floyd_steinberg(img, &palette.colors, strength),
+        Dither::Ordered => map_ordered(img, &palette.colors, strength),
+    };
+
+    IndexedImage {
+        width: img.width(),
+        height: img.height(),
+        palette: palette.colors,
+        indices,
+    }
+}
+
+// GIF only knows fully transparent or fully opaque pixels, so snap alpha
+// before quantizing to keep a single transparent palette entry
+pub fn prepare_for_gif(img: &mut RgbaImage) {
+    for pixel in img.pixels_mut() {
+        if pixel[3] < 128 {
+            pixel.0 = [0, 0, 0, 0];
+        } else {
+            pixel[3] = 255;
+        }
+    }
+}
+
+struct Palette {
+    colors: Vec<[u8; 4]>,
+    exact: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Bucket {
+    sum: [u64; 4],
+    count: u64,
+}
+
+impl Bucket {
+    fn mean(&self) -> [u8; 4] {
+        let mut color = [0u8; 4];
+        for (c, sum) in color.iter_mut().zip(self.sum) {
+            *c = ((sum + self.count / 2) / self.count) as u8;
+        }
+        color
+    }
+}
+
+fn build_palette(img: &RgbaImage, max_colors: usize) -> Palette {
+    // Flat graphics often fit the palette exactly, keep their colors untouched
+    let mut unique: HashMap<[u8; 4], u64> = HashMap::new();
+    for pixel in img.pixels() {
+        *unique.entry(pixel.0).or_insert(0) += 1;
+        if unique.len() > max_colors {
+            break;
+        }
+    }
+    if unique.len() <= max_colors {
+        let mut colors: Vec<[u8; 4]> = unique.into_keys().collect();
+        colors.sort_unstable();
+        if colors.is_empty() {
+            colors.push([0, 0, 0, 255]);
+        }
+        return Palette {
+            colors,
+            exact: true,
+        };
+    }
+
+    // Histogram with 5 bits per channel keeps median cut fast on photos
+    let mut histogram: HashMap<[u8; 4], Bucket> = HashMap::new();
+    for pixel in img.pixels() {
+        let key = pixel.0.map(|c| c >> 3);
+        let bucket = histogram.entry(key).or_insert(Bucket {
+            sum: [0; 4],
+            count: 0,
+        });
+        for (sum, c) in bucket.sum.iter_mut().zip(pixel.0) {
+            *sum += c as u64;
+        }
+        bucket.count += 1;
+    }
+
+    let mut boxes: Vec<Vec<Bucket>> = vec![histogram.into_values().collect()];
+    while boxes.len() < max_colors {
+        // Split the box with the widest channel range
+        let candidate = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.len() > 1)
+            .map(|(i, b)| (i, widest_channel(b)))
+            .max_by_key(|(_, (_, range))| *range);
+
+        let Some((index, (channel, _))) = candidate else {
+            break;
+        };
+
+        let mut entries = boxes.swap_remove(index);
+        entries.sort_unstable_by_key(|b| b.mean()[channel]);
+
+        let total: u64 = entries.iter().map(|b| b.count).sum();
+        let mut running = 0;
+        let mut split = 1;
+        for (i, bucket) in entries.iter().enumerate() {
+            running += bucket.count;
+            if running * 2 >= total {
+                split = (i + 1).clamp(1, entries.len() - 1);
+                break;
+            }
+        }
+
+        let upper = entries.split_off(split);
+        boxes.push(entries);
+        boxes.push(upper);
+    }
+
+    let colors = boxes
+        .iter()
+        .map(|entries| {
+            let mut merged = Bucket {
+                sum: [0; 4],
+                count: 0,
+            };
+            for bucket in entries {
+                for (sum, s) in merged.sum.iter_mut().zip(bucket.sum) {
+                    *sum += s;
+                }
+                merged.count += bucket.count;
+            }
+            merged.mean()
+        })
+        .collect();
+
+    Palette {
+        colors,
+        exact: false,
+    }
+}
+
+fn widest_channel(entries: &[Bucket]) -> (usize, u8) {
+    let mut min = [u8::MAX; 4];
+    let mut max = [u8::MIN; 4];
+    for bucket in entries {
+        let color = bucket.mean();
+        for c in 0..4 {
+            min[c] = min[c].min(color[c]);
+            max[c] = max[c].max(color[c]);
+        }
+    }
+
+    (0..4)
+        .map(|c| (c, max[c] - min[c]))
+        .max_by_key(|(_, range)| *range)
+        .unwrap_or((0, 0))
+}
+
+fn nearest_index(palette: &[[u8; 4]], color: [f32; 4]) -> usize {
+    let mut best = 0;
+    let mut best_distance = f32::MAX;
+    for (i, entry) in palette.iter().enumerate() {
+        let distance: f32 = entry
+            .iter()
+            .zip(color)
+            .map(|(&p, c)| {
+                let d = p as f32 - c;
+                d * d
+            })
+            .sum();
+        if distance < best_distance {
+            best_distance = distance;
+            best = i;
+        }
+    }
+    best
+}
+
+fn to_f32(color: [u8; 4]) -> [f32; 4] {
+    color.map(|c| c as f32)
+}
+
+fn map_nearest(img: &RgbaImage, palette: &[[u8; 4]]) -> Vec<u8> {
+    let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+    img.pixels()
+        .map(|pixel| {
+            *cache
+                .entry(pixel.0)
+                .or_insert_with(|| nearest_index(palette, to_f32(pixel.0)) as u8)
+        })
+        .collect()
+}
+
+fn map_floyd_steinberg(img: &RgbaImage, palette: &[[u8; 4]], strength: f32) -> Vec<u8> {
+    let width = img.width() as usize;
+    let mut indices = Vec::with_capacity(width * img.height() as usize);
+
+    // Error rows carry one pixel of padding on each side
+    let mut current = vec![[0f32; 4]; width + 2];
+    let mut next = vec![[0f32; 4]; width + 2];
+
+    for row in img.rows() {
+        for (x, pixel) in row.enumerate() {
+            let mut color = to_f32(pixel.0);
+            for c in 0..4 {
+                color[c] = (color[c] + current[x + 1][c]).clamp(0.0, 255.0);
+            }
+
+            let index = nearest_index(palette, color);
+            indices.push(index as u8);
+
+            let chosen = to_f32(palette[index]);
+            for c in 0..4 {
+                let error = (color[c] - chosen[c]) * strength;
+                current[x + 2][c] += error * 7.0 / 16.0;
+                next[x][c] += error * 3.0 / 16.0;
+                next[x + 1][c] += error * 5.0 / 16.0;
+                next[x + 2][c] += error / 16.0;
+            }
+        }
+
+        std::mem::swap(&mut current, &mut next);
+        next.iter_mut().for_each(|e| *e = [0.0; 4]);
+    }
+
+    indices
+}
+
+fn map_ordered(img: &RgbaImage, palette: &[[u8; 4]], strength: f32) -> Vec<u8> {
+    // Spread the threshold over roughly one palette step per channel
+    let spread = 255.0 / (palette.len() as f32).cbrt() * strength;
+
+    img.enumerate_pixels()
+        .map(|(x, y, pixel)| {
+            let threshold = BAYER_8X8[(y % 8) as usize][(x % 8) as usize] as f32 / 64.0 - 0.5;
+            let mut color = to_f32(pixel.0);
+            for c in color.iter_mut().take(3) {
+                *c = (*c + threshold * spread).clamp(0.0, 255.0);
+            }
+            nearest_index(palette, color) as u8
+        })
+        .collect()
+}
+
+pub fn write_gif<W: Write>(indexed: &IndexedImage, writer: W) -> Result<(), Error> {
+    let width = u16::try_from(indexed.width)
+        .map_err(|_| Error::other("GIF width cannot exceed 65535 pixels"))?;
+    let height = u16::try_from(indexed.height)
+        .map_err(|_| Error::other("GIF height cannot exceed 65535 pixels"))?;
+
+    let palette: Vec<u8> = indexed
+        .palette
+        .iter()
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+    let transparent = indexed
+        .palette
+        .iter()
+        .position(|c| c[3] == 0)
+        .map(|i| i as u8);
+
+    let mut encoder = gif::Encoder::new(writer, width, height, &[])
+        .map_err(|e| Error::other(format!("GIF encoding failed: {}", e)))?;
+    let frame = gif::Frame::from_palette_pixels(
+        width,
+        height,
+        indexed.indices.clone(),
+        palette,
+        transparent,
+    );
+    encoder
+        .write_frame(&frame)
+        .map_err(|e| Error::other(format!("GIF encoding failed: {}", e)))?;
+
+    Ok(())
+}
+
+pub fn write_indexed_png<W: Write>(indexed: &IndexedImage, writer: W) -> Result<(), Error> {
+    // Small palettes pack several pixels into each byte
+    let (depth, bits) = match indexed.palette.len() {
+        0..=2 => (png::BitDepth::One, 1),
+        3..=4 => (png::BitDepth::Two, 2),
+        5..=16 => (png::BitDepth::Four, 4),
+        _ => (png::BitDepth::Eight, 8),
+    };
+
+    let palette: Vec<u8> = indexed
+        .palette
+        .iter()
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+
+    let mut encoder = png::Encoder::new(writer, indexed.width, indexed.height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(palette);
+
+    // Only write a tRNS chunk when some entry is actually translucent
+    if indexed.palette.iter().any(|c| c[3] < 255) {
+        let trns: Vec<u8> = indexed.palette.iter().map(|c| c[3]).collect();
+        encoder.set_trns(trns);
+    }
+
+    let data = pack_rows(&indexed.indices, indexed.width as usize, bits);
+
+    let mut png_writer = encoder
+        .write_header()
+        .map_err(|e| Error::other(format!("PNG encoding failed: {}", e)))?;
+    png_writer
+        .write_image_data(&data)
+        .map_err(|e| Error::other(format!("PNG encoding failed: {}", e)))?;
+
+    Ok(())
+}
+
+fn pack_rows(indices: &[u8], width: usize, bits: usize) -> Vec<u8> {
+    if bits == 8 || width == 0 {
+        return indices.to_vec();
+    }
+
+    let per_byte = 8 / bits;
+    let row_bytes = width.div_ceil(per_byte);
+    let mut data = Vec::with_capacity(row_bytes * indices.len() / width);
+
+    for row in indices.chunks(width) {
+        for group in row.chunks(per_byte) {
+            let mut byte = 0u8;
+            for (i, &index) in group.iter().enumerate() {
+                byte |= index << (8 - bits * (i + 1));
+            }
+            data.push(byte);
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 7) as u8, (y * 5) as u8, ((x + y) * 3) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn palette_never_exceeds_max_colors() {
+        let img = gradient(64, 64);
+        for max_colors in [2, 3, 16, 100, 256] {
+            for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+                let options = QuantizeOptions {
+                    max_colors,
+                    dither,
+                    ..QuantizeOptions::default()
+                };
+                let indexed = quantize(&img, &options);
+                assert!(indexed.palette.len() <= max_colors as usize);
+                assert_eq!(indexed.indices.len(), 64 * 64);
+                assert!(indexed
+                    .indices
+                    .iter()
+                    .all(|&i| (i as usize) < indexed.palette.len()));
+            }
+        }
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 128]];
+        let img = RgbaImage::from_fn(9, 5, |x, y| Rgba(colors[((x + y) % 3) as usize]));
+        let indexed = quantize(&img, &QuantizeOptions::default());
+        assert_eq!(indexed.palette.len(), 3);
+        for (pixel, &index) in img.pixels().zip(&indexed.indices) {
+            assert_eq!(indexed.palette[index as usize], pixel.0);
+        }
+    }
+
+    #[test]
+    fn pack_rows_pads_each_row() {
+        // Three 2-bit pixels per row fit in one byte, the rest is padding
+        let packed = pack_rows(&[1, 2, 3, 3, 0, 1], 3, 2);
+        assert_eq!(packed, vec![0b0110_1100, 0b1100_0100]);
+        assert_eq!(
+            pack_rows(&[1, 0, 1, 1, 0, 0, 0, 0, 1], 9, 1),
+            vec![0b1011_0000, 0b1000_0000]
+        );
+        assert_eq!(pack_rows(&[5, 6, 7], 3, 8), vec![5, 6, 7]);
+    }
+
+    #[test]
+    fn indexed_png_decodes_to_the_palette_colors() {
+        let colors = [[10, 20, 30, 255], [200, 100, 50, 0], [0, 0, 0, 255]];
+        let img = RgbaImage::from_fn(5, 3, |x, y| Rgba(colors[((x * y) % 3) as usize]));
+        let indexed = quantize(&img, &QuantizeOptions::default());
+
+        let mut png = Vec::new();
+        write_indexed_png(&indexed, &mut png).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(decoded, img);
+    }
+}
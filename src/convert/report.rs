@@ -0,0 +1,323 @@
+use image::ImageFormat;
+use std::fmt::Write as _;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::{
+    extension_mismatch, BatchSummary, ConversionPanic, ConvertStats, ConvertedFile,
+    ExtensionMismatch,
+};
+
+// One row of a batch report: an output that was written or a failure
+#[derive(Debug, Clone)]
+pub struct FileRecord {
+    pub input: PathBuf,
+    // Detected from the content
+    pub input_format: Option<ImageFormat>,
+    pub extension_mismatch: Option<ExtensionMismatch>,
+    pub input_bytes: Option<u64>,
+    pub output: Option<PathBuf>,
+    pub stats: Option<ConvertStats>,
+    pub rule: Option<String>,
+    // Time spent on the whole input, shared by all of its outputs
+    pub duration: Duration,
+    pub error_kind: Option<&'static str>,
+    pub error: Option<String>,
+}
+
+impl FileRecord {
+    pub(super) fn converted(converted: &ConvertedFile, duration: Duration) -> Vec<FileRecord> {
+        // A fixed input is only readable under its new name
+        let source = converted
+            .extension_mismatch
+            .as_ref()
+            .and_then(|m| m.renamed_to.as_deref())
+            .unwrap_or(&converted.input);
+        converted
+            .outputs
+            .iter()
+            .map(|(output, stats)| FileRecord {
+                input: converted.input.clone(),
+                extension_mismatch: converted.extension_mismatch.clone(),
+                output: Some(output.clone()),
+                stats: Some(stats.clone()),
+                rule: converted.rule.clone(),
+                ..FileRecord::new(source, duration)
+            })
+            .collect()
+    }
+
+    pub(super) fn failed(
+        input: &Path,
+        output: Option<PathBuf>,
+        error: &Error,
+        duration: Duration,
+    ) -> FileRecord {
+        FileRecord {
+            output,
+            error_kind: Some(error_kind(error)),
+            error: Some(error.to_string()),
+            ..FileRecord::new(input, duration)
+        }
+    }
+
+    fn new(input: &Path, duration: Duration) -> FileRecord {
+        let extension_mismatch = extension_mismatch(input);
+        FileRecord {
+            input: input.to_path_buf(),
+            input_format: extension_mismatch
+                .as_ref()
+                .map(|m| m.detected)
+                .or_else(|| ImageFormat::from_path(input).ok()),
+            extension_mismatch,
+            input_bytes: std::fs::metadata(input).ok().map(|m| m.len()),
+            output: None,
+            stats: None,
+            rule: None,
+            duration,
+            error_kind: None,
+            error: None,
+        }
+    }
+
+    // Input size over output size, above 1 when the output is smaller
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let output_bytes = self.stats.as_ref()?.output_bytes;
+        let input_bytes = self.input_bytes?;
+        (output_bytes > 0).then(|| input_bytes as f64 / output_bytes as f64)
+    }
+}
+
+// Short machine-readable name for why a file failed
+pub fn error_kind(error: &Error) -> &'static str {
+    if error
+        .get_ref()
+        .is_some_and(|inner| inner.is::<ConversionPanic>())
+    {
+        return "panic";
+    }
+    match error.kind() {
+        ErrorKind::NotFound => "not_found",
+        ErrorKind::PermissionDenied => "permission_denied",
+        ErrorKind::InvalidData => "decode",
+        ErrorKind::Unsupported => "unsupported",
+        ErrorKind::OutOfMemory => "too_large",
+        ErrorKind::TimedOut => "timeout",
+        _ => "conversion",
+    }
+}
+
+const CSV_HEADER: &str =
+    "input,input_format,extension_mismatch,input_bytes,output,output_format,width,height,\
+output_bytes,compression_ratio,quality,psnr,ssim,max_delta,rule,duration_ms,error_kind,error";
+
+impl BatchSummary {
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(CSV_HEADER);
+        csv.push('\n');
+        for record in &self.records {
+            let stats = record.stats.as_ref();
+            let metrics = stats.and_then(|s| s.metrics.as_ref());
+            let fields = [
+                record.input.display().to_string(),
+                optional(record.input_format.map(format_name)),
+                optional(record.extension_mismatch.as_ref().map(|m| m.describe())),
+                optional(record.input_bytes),
+                optional(record.output.as_ref().map(|p| p.display())),
+                optional(stats.map(|s| s.format.extension().to_string())),
+                optional(stats.map(|s| s.width)),
+                optional(stats.map(|s| s.height)),
+                optional(stats.map(|s| s.output_bytes)),
+                optional(record.compression_ratio().map(|r| format!("{:.3}", r))),
+                optional(stats.and_then(|s| s.quality)),
+                optional(metrics.map(|m| format!("{:.2}", m.psnr))),
+                optional(metrics.map(|m| format!("{:.4}", m.ssim))),
+                optional(metrics.map(|m| m.max_delta)),
+                optional(record.rule.as_ref()),
+                record.duration.as_millis().to_string(),
+                optional(record.error_kind),
+                optional(record.error.as_ref()),
+            ];
+            let fields: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let _ = writeln!(json, "  \"success_count\": {},", self.success_count);
+        let _ = writeln!(json, "  \"error_count\": {},", self.error_count);
+        json.push_str("  \"files\": [\n");
+        for (i, record) in self.records.iter().enumerate() {
+            let stats = record.stats.as_ref();
+            let metrics = stats.and_then(|s| s.metrics.as_ref());
+            let fields = [
+                ("input", json_string(&record.input.display().to_string())),
+                (
+                    "input_format",
+                    json_optional(record.input_format.map(|f| json_string(format_name(f)))),
+                ),
+                (
+                    "extension_mismatch",
+                    json_optional(
+                        record
+                            .extension_mismatch
+                            .as_ref()
+                            .map(|m| json_string(&m.describe())),
+                    ),
+                ),
+                ("input_bytes", json_optional(record.input_bytes)),
+                (
+                    "output",
+                    json_optional(
+                        record
+                            .output
+                            .as_ref()
+                            .map(|p| json_string(&p.display().to_string())),
+                    ),
+                ),
+                (
+                    "output_format",
+                    json_optional(stats.map(|s| json_string(s.format.extension()))),
+                ),
+                ("width", json_optional(stats.map(|s| s.width))),
+                ("height", json_optional(stats.map(|s| s.height))),
+                ("output_bytes", json_optional(stats.map(|s| s.output_bytes))),
+                (
+                    "compression_ratio",
+                    json_optional(record.compression_ratio().map(|r| format!("{:.3}", r))),
+                ),
+                ("quality", json_optional(stats.and_then(|s| s.quality))),
+                // JSON has no infinity, a lossless output reports null
+                (
+                    "psnr",
+                    json_optional(
+                        metrics
+                            .filter(|m| m.psnr.is_finite())
+                            .map(|m| format!("{:.2}", m.psnr)),
+                    ),
+                ),
+                (
+                    "ssim",
+                    json_optional(metrics.map(|m| format!("{:.4}", m.ssim))),
+                ),
+                ("max_delta", json_optional(metrics.map(|m| m.max_delta))),
+                (
+                    "rule",
+                    json_optional(record.rule.as_deref().map(json_string)),
+                ),
+                ("duration_ms", record.duration.as_millis().to_string()),
+                (
+                    "error_kind",
+                    json_optional(record.error_kind.map(json_string)),
+                ),
+                (
+                    "error",
+                    json_optional(record.error.as_deref().map(json_string)),
+                ),
+            ];
+            let fields: Vec<String> = fields
+                .iter()
+                .map(|(key, value)| format!("\"{}\": {}", key, value))
+                .collect();
+            let _ = write!(json, "    {{{}}}", fields.join(", "));
+            json.push_str(if i + 1 < self.records.len() {
+                ",\n"
+            } else {
+                "\n"
+            });
+        }
+        json.push_str("  ]\n}\n");
+        json
+    }
+
+    // The format follows the extension, .csv or .json
+    pub fn write_report(&self, path: &Path) -> Result<(), Error> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let contents = match extension.as_deref() {
+            Some("csv") => self.to_csv(),
+            Some("json") => self.to_json(),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Report file must end in .csv or .json",
+                ))
+            }
+        };
+        std::fs::write(path, contents)
+    }
+}
+
+fn format_name(format: ImageFormat) -> &'static str {
+    format
+        .extensions_str()
+        .first()
+        .copied()
+        .unwrap_or("unknown")
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+pub(super) fn json_optional(value: Option<impl ToString>) -> String {
+    value.map_or("null".to_string(), |v| v.to_string())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+pub(super) fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("photo.jpg"), "photo.jpg");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b.png"), "\"a,b.png\"");
+        assert_eq!(csv_field("say \"cheese\""), "\"say \"\"cheese\"\"\"");
+        assert_eq!(csv_field("line\nbreak"), "\"line\nbreak\"");
+        assert_eq!(csv_field("carriage\rreturn"), "\"carriage\rreturn\"");
+    }
+
+    #[test]
+    fn json_string_escapes_specials() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(json_string("a\"b"), "\"a\\\"b\"");
+        assert_eq!(json_string("C:\\images"), "\"C:\\\\images\"");
+        assert_eq!(json_string("a\nb\rc\td"), "\"a\\nb\\rc\\td\"");
+        assert_eq!(json_string("\u{1}\u{1f}"), "\"\\u0001\\u001f\"");
+        assert_eq!(json_string("café 写真"), "\"café 写真\"");
+    }
+}
@@ -0,0 +1,419 @@
+use image::imageops::FilterType;
+use std::fmt::Write as _;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use super::report::json_string;
+use super::{
+    check_extension, encode_to_file, load_image, run_batch_outputs, write_output, BatchSummary,
+    ClaimedNames, ConvertFormat, ConvertOptions, ConvertStats, ConvertedFile, FailedOutput,
+    OutputTarget,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponsiveSet {
+    // Output widths in pixels, widths above the source width are skipped
+    pub widths: Vec<u32>,
+    // `sizes` attribute written into the markup
+    pub sizes: String,
+}
+
+impl Default for ResponsiveSet {
+    fn default() -> Self {
+        ResponsiveSet {
+            widths: vec![320, 640, 1280, 1920],
+            sizes: "100vw".to_string(),
+        }
+    }
+}
+
+// Writes `{stem}-{width}w.{ext}` for every width and target next to the input,
+// plus `{stem}.html` with a <picture> element and `{stem}.json` describing the
+// set. The first target is the <img> fallback, the others become <source>s.
+// When a file of the set is taken the whole set is numbered instead, like
+// `{stem}_converted_1-320w.webp`. Outputs that fail are left out of the
+// markup and returned next to the file's written ones.
+fn convert_responsive(
+    input_path: &Path,
+    targets: &[OutputTarget],
+    set: &ResponsiveSet,
+    options: &ConvertOptions,
+    overwrite: bool,
+    claimed: &ClaimedNames,
+) -> Result<(ConvertedFile, Vec<FailedOutput>), Error> {
+    if targets.is_empty() {
+        return Err(Error::other("No output formats selected"));
+    }
+
+    let img = load_image(input_path, options)?;
+    let stem = input_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("converted");
+    let base_path = input_path.parent().unwrap_or(Path::new("."));
+
+    let mut widths: Vec<u32> = set
+        .widths
+        .iter()
+        .copied()
+        .filter(|&w| w > 0 && w <= img.width())
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
+    // A source narrower than every width still gets one image at its own size
+    if widths.is_empty() {
+        widths.push(img.width());
+    }
+
+    // Images in width and then target order, followed by the markup and manifest
+    let mut paths = claimed.claim_group(overwrite, |number| {
+        let prefix = match number {
+            Some(n) => format!("{}_converted_{}", stem, n),
+            None => stem.to_string(),
+        };
+        let mut names = Vec::new();
+        for width in &widths {
+            for target in targets {
+                names.push(format!(
+                    "{}-{}w.{}",
+                    prefix,
+                    width,
+                    target.format.extension()
+                ));
+            }
+        }
+        names.push(format!("{}.html", prefix));
+        names.push(format!("{}.json", prefix));
+        names.into_iter().map(|name| base_path.join(name)).collect()
+    });
+    let manifest_path = paths.pop().expect("claimed with the set");
+    let markup_path = paths.pop().expect("claimed with the set");
+
+    let mut outputs = Vec::new();
+    let mut failed = Vec::new();
+    let mut paths = paths.into_iter();
+    for &width in &widths {
+        let scaled = if width == img.width() {
+            img.clone()
+        } else {
+            let height =
+                ((img.height() as f64 * width as f64 / img.width() as f64).round() as u32).max(1);
+            img.resize_exact(width, height, FilterType::Lanczos3)
+        };
+
+        for (target, output_path) in targets.iter().zip(paths.by_ref()) {
+            match encode_to_file(&scaled, &output_path, target, options) {
+                Ok(stats) => outputs.push((output_path, stats)),
+                Err(error) => failed.push(FailedOutput {
+                    path: output_path,
+                    format: target.format.clone(),
+                    error,
+                }),
+            }
+        }
+    }
+    if outputs.is_empty() {
+        let first = failed.remove(0);
+        return Err(first.error);
+    }
+
+    let described = write_output(
+        &markup_path,
+        picture_markup(targets, &outputs, &set.sizes).as_bytes(),
+    )
+    .and_then(|_| {
+        write_output(
+            &manifest_path,
+            manifest(input_path, &outputs, &set.sizes).as_bytes(),
+        )
+    });
+    if let Err(e) = described {
+        return Err(Error::new(
+            e.kind(),
+            format!("Wrote {} images but not their markup: {}", outputs.len(), e),
+        ));
+    }
+
+    println!("Generated responsive set for {}", input_path.display());
+    let converted = ConvertedFile {
+        input: input_path.to_path_buf(),
+        outputs,
+        rule: None,
+        extension_mismatch: check_extension(input_path, options),
+    };
+    Ok((converted, failed))
+}
+
+pub fn convert_responsive_batch(
+    files: Vec<PathBuf>,
+    targets: &[OutputTarget],
+    set: &ResponsiveSet,
+    options: &ConvertOptions,
+    overwrite: bool,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+) -> BatchSummary {
+    let targets_clone = targets.to_vec();
+    let set_clone = set.clone();
+    let options_clone = options.clone();
+    let claimed = ClaimedNames::default();
+    run_batch_outputs(
+        files,
+        options,
+        Vec::new(),
+        progress_callback,
+        move |file_path| {
+            convert_responsive(
+                file_path,
+                &targets_clone,
+                &set_clone,
+                &options_clone,
+                overwrite,
+                &claimed,
+            )
+        },
+    )
+}
+
+// Browsers take the first <source> they support, so the most efficient
+// formats go first
+fn source_rank(format: &ConvertFormat) -> u8 {
+    match format {
+        ConvertFormat::Avif => 0,
+        ConvertFormat::Webp => 1,
+        _ => 2,
+    }
+}
+
+fn picture_markup(
+    targets: &[OutputTarget],
+    outputs: &[(PathBuf, ConvertStats)],
+    sizes: &str,
+) -> String {
+    let srcset = |target: &OutputTarget| {
+        let entries: Vec<String> = outputs
+            .iter()
+            .filter(|(_, stats)| stats.format == target.format)
+            .map(|(path, stats)| format!("{} {}w", url_file_name(path), stats.width))
+            .collect();
+        escape_attribute(&entries.join(", "))
+    };
+    let sizes = escape_attribute(sizes);
+
+    // Formats that failed for every width are left out
+    let written: Vec<&OutputTarget> = targets
+        .iter()
+        .filter(|target| {
+            outputs
+                .iter()
+                .any(|(_, stats)| stats.format == target.format)
+        })
+        .collect();
+    let mut html = String::from("<picture>\n");
+    let Some((fallback, sources)) = written.split_first() else {
+        html.push_str("</picture>\n");
+        return html;
+    };
+    let mut sources = sources.to_vec();
+    sources.sort_by_key(|target| source_rank(&target.format));
+    for target in sources {
+        let _ = writeln!(
+            html,
+            "  <source type=\"{}\" srcset=\"{}\" sizes=\"{}\">",
+            target.format.image_format().to_mime_type(),
+            srcset(target),
+            sizes
+        );
+    }
+
+    // The widest fallback image doubles as `src` for browsers without srcset
+    if let Some((path, stats)) = outputs
+        .iter()
+        .filter(|(_, stats)| stats.format == fallback.format)
+        .max_by_key(|(_, stats)| stats.width)
+    {
+        let _ = writeln!(
+            html,
+            "  <img src=\"{}\" srcset=\"{}\" sizes=\"{}\" width=\"{}\" height=\"{}\" alt=\"\">",
+            escape_attribute(&url_file_name(path)),
+            srcset(fallback),
+            sizes,
+            stats.width,
+            stats.height
+        );
+    }
+    html.push_str("</picture>\n");
+    html
+}
+
+fn manifest(input_path: &Path, outputs: &[(PathBuf, ConvertStats)], sizes: &str) -> String {
+    let source = input_path.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut json = String::from("{\n");
+    let _ = writeln!(json, "  \"source\": {},", json_string(&source));
+    let _ = writeln!(json, "  \"sizes\": {},", json_string(sizes));
+    json.push_str("  \"images\": [\n");
+    for (i, (path, stats)) in outputs.iter().enumerate() {
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        let quality = stats.quality.map_or("null".to_string(), |q| q.to_string());
+        let _ = write!(
+            json,
+            "    {{\"file\": {}, \"format\": {}, \"mime\": {}, \"width\": {}, \"height\": {}, \"bytes\": {}, \"quality\": {}}}",
+            json_string(&file),
+            json_string(stats.format.extension()),
+            json_string(stats.format.image_format().to_mime_type()),
+            stats.width,
+            stats.height,
+            stats.output_bytes,
+            quality
+        );
+        json.push_str(if i + 1 < outputs.len() { ",\n" } else { "\n" });
+    }
+    json.push_str("  ]\n}\n");
+    json
+}
+
+// Spaces and commas would split a srcset candidate
+fn url_file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace(',', "%2C")
+}
+
+fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::super::OutputColorType;
+    use super::*;
+
+    fn stats(format: ConvertFormat, width: u32) -> ConvertStats {
+        ConvertStats {
+            format,
+            width,
+            height: width / 2,
+            output_bytes: 100,
+            quality: None,
+            metrics: None,
+        }
+    }
+
+    #[test]
+    fn sources_put_efficient_formats_first() {
+        // The order the GUI builds from its format list
+        let targets: Vec<OutputTarget> = [
+            ConvertFormat::Jpeg,
+            ConvertFormat::Webp,
+            ConvertFormat::Avif,
+        ]
+        .into_iter()
+        .map(OutputTarget::new)
+        .collect();
+        let outputs: Vec<(PathBuf, ConvertStats)> = targets
+            .iter()
+            .map(|t| {
+                let path = PathBuf::from(format!("photo-320w.{}", t.format.extension()));
+                (path, stats(t.format.clone(), 320))
+            })
+            .collect();
+
+        let html = picture_markup(&targets, &outputs, "100vw");
+        let avif = html.find("image/avif").unwrap();
+        let webp = html.find("image/webp").unwrap();
+        let img = html.find("<img src=\"photo-320w.jpg\"").unwrap();
+        assert!(avif < webp && webp < img, "{}", html);
+    }
+
+    fn set() -> ResponsiveSet {
+        ResponsiveSet {
+            widths: vec![4, 8],
+            sizes: "50vw".to_string(),
+        }
+    }
+
+    #[test]
+    fn existing_sets_are_kept_unless_overwriting() {
+        let dir = temp_dir("responsive_collision");
+        let input = write_png(&dir, "photo.png", 8, 8);
+        let targets = [OutputTarget::new(ConvertFormat::Png)];
+        let options = ConvertOptions::default();
+
+        let run = |overwrite| {
+            convert_responsive_batch(
+                vec![input.clone()],
+                &targets,
+                &set(),
+                &options,
+                overwrite,
+                |_, _| {},
+            )
+        };
+        assert_eq!(run(false).success_count, 2);
+        assert!(dir.join("photo-4w.png").exists());
+        let markup = std::fs::read(dir.join("photo.html")).unwrap();
+
+        assert_eq!(run(false).success_count, 2);
+        assert!(dir.join("photo_converted_1-8w.png").exists());
+        assert!(dir.join("photo_converted_1.json").exists());
+        assert_eq!(std::fs::read(dir.join("photo.html")).unwrap(), markup);
+
+        run(true);
+        assert!(!dir.join("photo_converted_2.html").exists());
+    }
+
+    #[test]
+    fn inputs_sharing_a_stem_get_their_own_sets() {
+        let dir = temp_dir("responsive_same_stem");
+        let png = write_png(&dir, "photo.png", 8, 8);
+        let webp = dir.join("photo.webp");
+        image::open(&png).unwrap().save(&webp).unwrap();
+        let targets = [OutputTarget::new(ConvertFormat::Bmp)];
+
+        let summary = convert_responsive_batch(
+            vec![png, webp],
+            &targets,
+            &set(),
+            &ConvertOptions::default(),
+            true,
+            |_, _| {},
+        );
+        assert_eq!(summary.success_count, 4);
+        assert!(dir.join("photo.html").exists());
+        assert!(dir.join("photo_converted_1.html").exists());
+    }
+
+    #[test]
+    fn failed_outputs_are_reported_and_left_out() {
+        let dir = temp_dir("responsive_partial");
+        let input = write_png(&dir, "photo.png", 8, 8);
+        // JPEG can't hold the alpha channel, PNG can
+        let targets = [
+            OutputTarget::new(ConvertFormat::Png),
+            OutputTarget::new(ConvertFormat::Jpeg),
+        ];
+        let options = ConvertOptions {
+            color_type: Some(OutputColorType::Rgba8),
+            ..ConvertOptions::default()
+        };
+
+        let summary =
+            convert_responsive_batch(vec![input], &targets, &set(), &options, false, |_, _| {});
+        assert_eq!(summary.success_count, 2);
+        assert_eq!(summary.error_count, 2);
+        assert!(summary.records.iter().any(|r| r
+            .output
+            .as_ref()
+            .is_some_and(|p| p.ends_with("photo-4w.jpg"))));
+        let markup = std::fs::read_to_string(dir.join("photo.html")).unwrap();
+        assert!(markup.contains("photo-8w.png") && !markup.contains(".jpg"));
+    }
+}
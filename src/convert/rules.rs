@@ -0,0 +1,240 @@
+use image::{DynamicImage, ImageFormat};
+use std::collections::HashSet;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+
+use super::transform::watermarks;
+use super::watermark::hold_sources;
+use super::{
+    catch_panic, check_extension, decode_image, encode_to_file, map_files, output_path_for,
+    prepare_image, run_batch, BatchSummary, ClaimedNames, ConvertFormat, ConvertOptions,
+    ConvertedFile, DecodeLimits, OutputTarget, Transform,
+};
+
+// More colors than this is treated as photographic
+const PHOTOGRAPHIC_COLORS: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleCondition {
+    // Has an alpha channel with at least one non-opaque pixel
+    HasAlpha,
+    // More distinct colors than a palette could hold well
+    Photographic,
+    MaxColors(usize),
+    WiderThan(u32),
+    SourceFormat(ConvertFormat),
+}
+
+impl RuleCondition {
+    pub fn describe(&self) -> String {
+        match self {
+            RuleCondition::HasAlpha => "has alpha".to_string(),
+            RuleCondition::Photographic => "photographic".to_string(),
+            RuleCondition::MaxColors(colors) => format!("≤{} colors", colors),
+            RuleCondition::WiderThan(width) => format!("width > {}", width),
+            RuleCondition::SourceFormat(format) => format!("{} source", format.name()),
+        }
+    }
+
+    fn matches(&self, traits: &ImageTraits) -> bool {
+        match self {
+            RuleCondition::HasAlpha => traits.has_alpha,
+            RuleCondition::Photographic => traits.colors > PHOTOGRAPHIC_COLORS,
+            RuleCondition::MaxColors(colors) => traits.colors <= *colors,
+            RuleCondition::WiderThan(width) => traits.width > *width,
+            RuleCondition::SourceFormat(format) => {
+                traits.source_format == Some(format.image_format())
+            }
+        }
+    }
+}
+
+// Rules are tried in order and the first whose conditions all hold decides
+// the output for a file
+#[derive(Debug, Clone)]
+pub struct FormatRule {
+    // An empty list matches every file
+    pub conditions: Vec<RuleCondition>,
+    pub target: OutputTarget,
+    // Run after the shared transforms, e.g. a resize for oversized inputs
+    pub transforms: Vec<Transform>,
+    pub indexed_png: bool,
+}
+
+impl FormatRule {
+    pub fn describe(&self) -> String {
+        let conditions: Vec<String> = self.conditions.iter().map(|c| c.describe()).collect();
+        let conditions = if conditions.is_empty() {
+            "always".to_string()
+        } else {
+            conditions.join(" and ")
+        };
+
+        let mut actions: Vec<String> = self
+            .transforms
+            .iter()
+            .map(|t| t.name().to_lowercase())
+            .collect();
+        if self.indexed_png && self.target.format == ConvertFormat::Png {
+            actions.push("indexed PNG".to_string());
+        } else {
+            actions.push(self.target.format.name().to_string());
+        }
+
+        format!("{} → {}", conditions, actions.join(" then "))
+    }
+}
+
+struct ImageTraits {
+    source_format: Option<ImageFormat>,
+    width: u32,
+    has_alpha: bool,
+    // Capped one above the highest count any condition looks at
+    colors: usize,
+}
+
+impl ImageTraits {
+    fn measure(
+        img: &DynamicImage,
+        source_format: Option<ImageFormat>,
+        rules: &[FormatRule],
+    ) -> Self {
+        let color_limit = rules
+            .iter()
+            .flat_map(|rule| &rule.conditions)
+            .filter_map(|condition| match condition {
+                RuleCondition::MaxColors(colors) => Some(*colors),
+                _ => None,
+            })
+            .fold(PHOTOGRAPHIC_COLORS, usize::max);
+
+        let rgba = img.to_rgba8();
+        let has_alpha = img.color().has_alpha() && rgba.pixels().any(|p| p[3] < 255);
+
+        let mut seen = HashSet::new();
+        for pixel in rgba.pixels() {
+            seen.insert(pixel.0);
+            if seen.len() > color_limit {
+                break;
+            }
+        }
+
+        ImageTraits {
+            source_format,
+            width: img.width(),
+            has_alpha,
+            colors: seen.len(),
+        }
+    }
+}
+
+fn pick_rule(rules: &[FormatRule], traits: &ImageTraits) -> Option<usize> {
+    rules
+        .iter()
+        .position(|rule| rule.conditions.iter().all(|c| c.matches(traits)))
+}
+
+// Dry run: the index of the rule each file would use, None for the fallback
+pub fn preview_rules(
+    files: &[PathBuf],
+    rules: &[FormatRule],
+    limits: &DecodeLimits,
+) -> Vec<(PathBuf, Result<Option<usize>, Error>)> {
+    map_files(
+        files,
+        limits,
+        &[],
+        |_, _| {},
+        |file_path, _reservation| {
+            let result = catch_panic(|| {
+                let (img, source_format) = decode_image(file_path, limits)?;
+                let traits = ImageTraits::measure(&img, source_format, rules);
+                Ok(pick_rule(rules, &traits))
+            });
+            (file_path.clone(), result)
+        },
+    )
+}
+
+// Files no rule matches are written as `fallback`
+pub fn convert_image_rules(
+    input_path: &Path,
+    rules: &[FormatRule],
+    fallback: &OutputTarget,
+    options: &ConvertOptions,
+    overwrite: bool,
+) -> Result<ConvertedFile, Error> {
+    let output_path = |format: &ConvertFormat| output_path_for(input_path, format, overwrite);
+    convert_rules(input_path, rules, fallback, options, output_path)
+}
+
+// `output_path` names the file once the rule has picked a format
+fn convert_rules(
+    input_path: &Path,
+    rules: &[FormatRule],
+    fallback: &OutputTarget,
+    options: &ConvertOptions,
+    output_path: impl FnOnce(&ConvertFormat) -> PathBuf,
+) -> Result<ConvertedFile, Error> {
+    let (img, source_format) = decode_image(input_path, &options.limits)?;
+    let traits = ImageTraits::measure(&img, source_format, rules);
+    let rule = pick_rule(rules, &traits).map(|index| &rules[index]);
+
+    let mut options = options.clone();
+    let target = match rule {
+        Some(rule) => {
+            options.transforms.extend(rule.transforms.iter().cloned());
+            options.quantize.indexed_png |= rule.indexed_png;
+            &rule.target
+        }
+        None => fallback,
+    };
+
+    let img = prepare_image(img, &options)?;
+    let output_path = output_path(&target.format);
+    let stats = encode_to_file(&img, &output_path, target, &options)?;
+    println!(
+        "Successfully converted {} to {} ({})",
+        input_path.display(),
+        output_path.display(),
+        rule.map_or("no rule matched".to_string(), |r| r.describe())
+    );
+
+    Ok(ConvertedFile {
+        input: input_path.to_path_buf(),
+        outputs: vec![(output_path, stats)],
+        rule: rule.map(|r| r.describe()),
+        extension_mismatch: check_extension(input_path, &options),
+    })
+}
+
+pub fn convert_batch_rules(
+    files: Vec<PathBuf>,
+    rules: &[FormatRule],
+    fallback: &OutputTarget,
+    options: &ConvertOptions,
+    overwrite: bool,
+    progress_callback: impl Fn(usize, usize) + Send + Sync + 'static,
+) -> BatchSummary {
+    let rules_clone = rules.to_vec();
+    let fallback_clone = fallback.clone();
+    let options_clone = options.clone();
+    let claimed = ClaimedNames::default();
+    // Only files a rule matches get its watermark, so a broken one fails just
+    // those, with the error kept from loading it once
+    let _sources = hold_sources(
+        rules.iter().flat_map(|rule| watermarks(&rule.transforms)),
+        &options.limits,
+    );
+    run_batch(files, options, progress_callback, move |file_path| {
+        let output_path =
+            |format: &ConvertFormat| claimed.claim_next_to(file_path, format, overwrite);
+        convert_rules(
+            file_path,
+            &rules_clone,
+            &fallback_clone,
+            &options_clone,
+            output_path,
+        )
+    })
+}
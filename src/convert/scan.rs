@@ -0,0 +1,302 @@
+use image::{ImageError, ImageFormat, ImageReader};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::inspect::decode_frames;
+use super::{
+    catch_panic, extension_mismatch, map_files, ConversionPanic, DecodeLimits, ExtensionMismatch,
+};
+
+// Enough to hold the last scan of a small image, or just the end of a big one
+const JPEG_TAIL_BYTES: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanProblem {
+    // The data ends before the image does
+    Truncated,
+    Corrupt,
+    // A format or feature this build cannot decode
+    Unsupported,
+    // Over the configured decode limits
+    TooLarge,
+    // The file itself could not be opened or read
+    Unreadable,
+}
+
+impl ScanProblem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScanProblem::Truncated => "truncated",
+            ScanProblem::Corrupt => "corrupt",
+            ScanProblem::Unsupported => "unsupported",
+            ScanProblem::TooLarge => "too_large",
+            ScanProblem::Unreadable => "unreadable",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub path: PathBuf,
+    // Detected from the content
+    pub format: Option<ImageFormat>,
+    pub dimensions: Option<(u32, u32)>,
+    pub frame_count: usize,
+    pub extension_mismatch: Option<ExtensionMismatch>,
+    pub problem: Option<(ScanProblem, String)>,
+}
+
+impl ScanResult {
+    pub fn is_ok(&self) -> bool {
+        self.problem.is_none() && self.extension_mismatch.is_none()
+    }
+
+    pub fn describe(&self) -> String {
+        let mut issues = Vec::new();
+        if let Some((problem, message)) = &self.problem {
+            issues.push(format!("{}: {}", problem.name(), message));
+        }
+        if let Some(mismatch) = &self.extension_mismatch {
+            issues.push(mismatch.describe());
+        }
+        if issues.is_empty() {
+            "ok".to_string()
+        } else {
+            issues.join("; ")
+        }
+    }
+}
+
+// Decodes the whole file, every frame of an animation included, without
+// writing anything
+pub fn scan_file(path: &Path, limits: &DecodeLimits) -> ScanResult {
+    let mut result = ScanResult {
+        path: path.to_path_buf(),
+        format: None,
+        dimensions: None,
+        frame_count: 0,
+        extension_mismatch: extension_mismatch(path),
+        problem: None,
+    };
+
+    let mut reader = match ImageReader::open(path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => reader,
+        Err(e) => {
+            result.problem = Some((ScanProblem::Unreadable, e.to_string()));
+            return result;
+        }
+    };
+    let Some(format) = reader.format() else {
+        result.problem = Some((
+            ScanProblem::Unsupported,
+            "Unrecognized image format".to_string(),
+        ));
+        return result;
+    };
+    result.format = Some(format);
+    reader.limits(limits.image_limits());
+
+    if is_cut_short(path, format) {
+        result.problem = Some((
+            ScanProblem::Truncated,
+            "File ends before the image data does".to_string(),
+        ));
+        return result;
+    }
+
+    // A decoder that panics has been handed data it doesn't expect.
+    // Animations are decoded frame by frame, anything else in one go.
+    let decoded = catch_panic(|| {
+        Ok(match decode_frames(path, format, limits) {
+            Ok(Some(frames)) => reader.into_dimensions().map(|size| (size, frames)),
+            Ok(None) => reader.decode().map(|img| ((img.width(), img.height()), 1)),
+            Err(e) => Err(e),
+        })
+    });
+    match decoded {
+        Ok(Ok((dimensions, frame_count))) => {
+            result.dimensions = Some(dimensions);
+            result.frame_count = frame_count;
+        }
+        Ok(Err(e)) => result.problem = Some((classify(&e), e.to_string())),
+        Err(e) => {
+            let message = match e
+                .get_ref()
+                .and_then(|e| e.downcast_ref::<ConversionPanic>())
+            {
+                Some(ConversionPanic(message)) => format!("Decoder panicked: {}", message),
+                None => e.to_string(),
+            };
+            result.problem = Some((ScanProblem::Corrupt, message));
+        }
+    }
+    result
+}
+
+pub fn scan_files(
+    files: &[PathBuf],
+    limits: &DecodeLimits,
+    progress_callback: impl Fn(usize, usize) + Send + Sync,
+) -> Vec<ScanResult> {
+    map_files(
+        files,
+        limits,
+        &[],
+        progress_callback,
+        |file_path, _reservation| scan_file(file_path, limits),
+    )
+}
+
+// Some decoders fill in missing data instead of failing, so the container is
+// checked for its end first. Only the header or the tail is read.
+fn is_cut_short(path: &Path, format: ImageFormat) -> bool {
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+    let Ok(length) = file.metadata().map(|m| m.len()) else {
+        return false;
+    };
+    match format {
+        // The end marker has to follow the last scan. Thumbnails carry their
+        // own markers but always come before the main image, and a main scan
+        // longer than the tail has no markers in it at all.
+        ImageFormat::Jpeg => {
+            let mut tail = Vec::new();
+            let start = length.saturating_sub(JPEG_TAIL_BYTES);
+            if file.seek(SeekFrom::Start(start)).is_err() || file.read_to_end(&mut tail).is_err() {
+                return false;
+            }
+            let scan = tail.windows(2).rposition(|w| w == [0xFF, 0xDA]);
+            !tail[scan.unwrap_or(0)..]
+                .windows(2)
+                .any(|w| w == [0xFF, 0xD9])
+        }
+        // The RIFF header states the size of everything after it
+        ImageFormat::WebP => {
+            let mut header = [0; 8];
+            match file.read_exact(&mut header) {
+                Ok(()) => {
+                    u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64 + 8
+                        > length
+                }
+                Err(_) => true,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn classify(error: &ImageError) -> ScanProblem {
+    match error {
+        ImageError::IoError(e) if e.kind() == ErrorKind::UnexpectedEof => ScanProblem::Truncated,
+        ImageError::IoError(_) => ScanProblem::Unreadable,
+        ImageError::Unsupported(_) => ScanProblem::Unsupported,
+        ImageError::Limits(_) => ScanProblem::TooLarge,
+        _ if is_truncation(error) => ScanProblem::Truncated,
+        _ => ScanProblem::Corrupt,
+    }
+}
+
+// Decoders report running out of data in their own words, so the whole
+// source chain is checked
+fn is_truncation(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if e.downcast_ref::<std::io::Error>()
+            .is_some_and(|io| io.kind() == ErrorKind::UnexpectedEof)
+        {
+            return true;
+        }
+        let message = e.to_string().to_lowercase();
+        if ["eof", "end of", "truncated", "not enough data"]
+            .iter()
+            .any(|hint| message.contains(hint))
+        {
+            return true;
+        }
+        current = e.source();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{gradient, temp_dir};
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{DynamicImage, Frame, RgbaImage};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        // Noise keeps the scan large
+        let img = RgbaImage::from_fn(width, height, |x, y| {
+            let v = (x.wrapping_mul(2654435761) ^ y.wrapping_mul(40503)) as u8;
+            image::Rgba([v, v.rotate_left(3), v.rotate_left(5), 255])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .to_rgb8()
+            .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Jpeg)
+            .unwrap();
+        bytes
+    }
+
+    fn scan_bytes(dir: &Path, name: &str, bytes: &[u8]) -> ScanResult {
+        let path = dir.join(name);
+        std::fs::write(&path, bytes).unwrap();
+        scan_file(&path, &DecodeLimits::default())
+    }
+
+    #[test]
+    fn finds_cut_jpegs_of_any_size() {
+        let dir = temp_dir("scan_jpeg");
+        for (name, size) in [("small.jpg", 16), ("large.jpg", 400)] {
+            let bytes = jpeg(size, size);
+            assert!(scan_bytes(&dir, name, &bytes).is_ok());
+
+            let cut = scan_bytes(&dir, name, &bytes[..bytes.len() * 2 / 3]);
+            assert_eq!(cut.problem.unwrap().0, ScanProblem::Truncated, "{}", name);
+        }
+        assert!(jpeg(400, 400).len() as u64 > JPEG_TAIL_BYTES);
+    }
+
+    #[test]
+    fn finds_cut_webps() {
+        let dir = temp_dir("scan_webp");
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(gradient(16, 16))
+            .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::WebP)
+            .unwrap();
+        assert!(scan_bytes(&dir, "whole.webp", &bytes).is_ok());
+
+        let cut = scan_bytes(&dir, "cut.webp", &bytes[..bytes.len() - 10]);
+        assert_eq!(cut.problem.unwrap().0, ScanProblem::Truncated);
+    }
+
+    #[test]
+    fn counts_every_frame_of_an_animation() {
+        let dir = temp_dir("scan_gif");
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut bytes);
+            for _ in 0..3 {
+                encoder.encode_frame(Frame::new(gradient(6, 4))).unwrap();
+            }
+        }
+        let result = scan_bytes(&dir, "anim.gif", &bytes);
+        assert!(result.is_ok(), "{}", result.describe());
+        assert_eq!(result.frame_count, 3);
+        assert_eq!(result.dimensions, Some((6, 4)));
+    }
+
+    #[test]
+    fn reports_a_mismatched_extension() {
+        let dir = temp_dir("scan_mismatch");
+        let result = scan_bytes(&dir, "photo.png", &jpeg(8, 8));
+        assert!(result.problem.is_none());
+        assert_eq!(
+            result.extension_mismatch.unwrap().detected,
+            ImageFormat::Jpeg
+        );
+    }
+}
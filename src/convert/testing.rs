@@ -0,0 +1,29 @@
+// Scratch files shared by the tests of the convert modules
+use image::{Rgba, RgbaImage};
+use std::path::{Path, PathBuf};
+
+// An empty folder of its own for every test, so they can run in parallel
+pub(super) fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("image_convert_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// Translucent, so every channel of the color conversions is exercised
+pub(super) fn gradient(width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        Rgba([
+            (x * 255 / width.max(2).saturating_sub(1)) as u8,
+            (y * 255 / height.max(2).saturating_sub(1)) as u8,
+            ((x + y) * 3) as u8,
+            200,
+        ])
+    })
+}
+
+pub(super) fn write_png(dir: &Path, name: &str, width: u32, height: u32) -> PathBuf {
+    let path = dir.join(name);
+    gradient(width, height).save(&path).unwrap();
+    path
+}
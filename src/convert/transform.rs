@@ -0,0 +1,245 @@
+use image::imageops::FilterType;
+use image::DynamicImage;
+use std::io::{Error, ErrorKind};
+
+use super::adjust::{apply_adjustments, Adjustments};
+use super::canvas::{fit_and_pad, Canvas};
+use super::filter::{gaussian_blur, unsharp_mask, UnsharpMask};
+use super::watermark::{apply_watermark, Watermark};
+use super::DecodeLimits;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resize {
+    // Bounding box to fit into, 0 leaves that side unconstrained
+    pub max_width: u32,
+    pub max_height: u32,
+    // Recover detail lost to downscaling
+    pub sharpen: Option<UnsharpMask>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transform {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Adjust(Adjustments),
+    Resize(Resize),
+    UnsharpMask(UnsharpMask),
+    GaussianBlur { sigma: f32 },
+    Watermark(Watermark),
+    FitAndPad(Canvas),
+}
+
+impl Transform {
+    pub fn name(&self) -> &str {
+        match self {
+            Transform::Rotate90 => "Rotate 90°",
+            Transform::Rotate180 => "Rotate 180°",
+            Transform::Rotate270 => "Rotate 270°",
+            Transform::FlipHorizontal => "Flip horizontal",
+            Transform::FlipVertical => "Flip vertical",
+            Transform::Adjust(_) => "Adjust colors",
+            Transform::Resize(_) => "Resize",
+            Transform::UnsharpMask(_) => "Sharpen",
+            Transform::GaussianBlur { .. } => "Blur",
+            Transform::Watermark(_) => "Watermark",
+            Transform::FitAndPad(_) => "Fit and pad",
+        }
+    }
+
+    // `limits` bound images the transform loads itself, like a watermark logo
+    pub fn apply(&self, img: DynamicImage, limits: &DecodeLimits) -> Result<DynamicImage, Error> {
+        let img = match self {
+            Transform::Rotate90 => img.rotate90(),
+            Transform::Rotate180 => img.rotate180(),
+            Transform::Rotate270 => img.rotate270(),
+            Transform::FlipHorizontal => img.fliph(),
+            Transform::FlipVertical => img.flipv(),
+            Transform::Adjust(adjustments) => apply_adjustments(img, adjustments),
+            Transform::Resize(resize) => apply_resize(img, resize),
+            Transform::UnsharpMask(mask) => unsharp_mask(img, mask),
+            Transform::GaussianBlur { sigma } => gaussian_blur(img, *sigma),
+            Transform::Watermark(watermark) => apply_watermark(img, watermark, limits)?,
+            Transform::FitAndPad(canvas) => {
+                check_canvas(canvas, limits)?;
+                fit_and_pad(img, canvas)
+            }
+        };
+        Ok(img)
+    }
+
+    // Size of the image this turns a `width` x `height` one into
+    fn output_size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self {
+            Transform::Rotate90 | Transform::Rotate270 => (height, width),
+            Transform::Resize(resize) => {
+                fit_within(width, height, resize.max_width, resize.max_height)
+            }
+            Transform::FitAndPad(canvas) => (canvas.width.max(1), canvas.height.max(1)),
+            _ => (width, height),
+        }
+    }
+}
+
+pub(super) fn watermarks(transforms: &[Transform]) -> impl Iterator<Item = &Watermark> {
+    transforms.iter().filter_map(|transform| match transform {
+        Transform::Watermark(watermark) => Some(watermark),
+        _ => None,
+    })
+}
+
+// The canvas is allocated as is, so it gets the same limits as a decoded image
+pub(super) fn check_canvas(canvas: &Canvas, limits: &DecodeLimits) -> Result<(), Error> {
+    let bytes = canvas.width as u64 * canvas.height as u64 * FLOAT_PIXEL_BYTES;
+    if canvas.width > limits.max_width
+        || canvas.height > limits.max_height
+        || bytes > limits.max_alloc
+    {
+        return Err(Error::new(
+            ErrorKind::OutOfMemory,
+            format!(
+                "Canvas of {}x{} exceeds the decode limits",
+                canvas.width, canvas.height
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// Transforms work on a copy with four f32 channels
+const FLOAT_PIXEL_BYTES: u64 = 16;
+
+// Bytes the transforms need on top of the decoded image at their peak, for
+// an image of `width` x `height`
+pub(super) fn working_bytes(transforms: &[Transform], width: u32, height: u32) -> u64 {
+    let (mut width, mut height) = (width, height);
+    let mut peak = 0;
+    for transform in transforms {
+        let (new_width, new_height) = transform.output_size(width, height);
+        let before = width as u64 * height as u64;
+        let after = new_width as u64 * new_height as u64;
+        let copies = |pixels: u64, count: u64| pixels * count * FLOAT_PIXEL_BYTES;
+        let bytes = match transform {
+            // The sharpened copy, the blurred one and the blur's own buffer
+            Transform::UnsharpMask(_) => copies(before, 3),
+            Transform::GaussianBlur { .. } => copies(before, 2),
+            Transform::Resize(resize) if resize.sharpen.is_some() => {
+                copies(before, 1).max(copies(after, 3))
+            }
+            // The fitted image and the canvas it is padded into
+            Transform::FitAndPad(_) => copies(after, 2),
+            _ => copies(before.max(after), 1),
+        };
+        peak = peak.max(bytes);
+        (width, height) = (new_width, new_height);
+    }
+    peak
+}
+
+fn apply_resize(img: DynamicImage, resize: &Resize) -> DynamicImage {
+    let (width, height) = fit_within(
+        img.width(),
+        img.height(),
+        resize.max_width,
+        resize.max_height,
+    );
+
+    // Never upscale, only shrink into the bounding box
+    if width >= img.width() && height >= img.height() {
+        return img;
+    }
+
+    let resized = img.resize_exact(width, height, FilterType::Lanczos3);
+    match &resize.sharpen {
+        Some(mask) => unsharp_mask(resized, mask),
+        None => resized,
+    }
+}
+
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    let max_width = if max_width == 0 { width } else { max_width };
+    let max_height = if max_height == 0 { height } else { max_height };
+
+    let scale = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    if scale >= 1.0 {
+        return (width, height);
+    }
+
+    (
+        ((width as f64 * scale).round() as u32).max(1),
+        ((height as f64 * scale).round() as u32).max(1),
+    )
+}
+
+// Transforms run in the order they were added
+pub fn apply_transforms(
+    img: DynamicImage,
+    transforms: &[Transform],
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, Error> {
+    transforms
+        .iter()
+        .try_fold(img, |img, transform| transform.apply(img, limits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn working_bytes_follow_the_image_size() {
+        assert_eq!(working_bytes(&[], 100, 100), 0);
+        // One float copy
+        assert_eq!(
+            working_bytes(&[Transform::Adjust(Adjustments::default())], 100, 50),
+            100 * 50 * 16
+        );
+        // Sharpening after a resize works on the smaller image
+        let resize = Transform::Resize(Resize {
+            max_width: 10,
+            max_height: 0,
+            sharpen: Some(UnsharpMask::default()),
+        });
+        assert_eq!(working_bytes(&[resize], 100, 100), 100 * 100 * 16);
+        // The canvas counts, not the image padded into it
+        let pad = Transform::FitAndPad(Canvas {
+            width: 400,
+            height: 300,
+            ..Canvas::default()
+        });
+        assert_eq!(
+            working_bytes(&[Transform::Rotate90, pad.clone()], 10, 10),
+            400 * 300 * 16 * 2
+        );
+        assert_eq!(
+            working_bytes(
+                &[pad, Transform::UnsharpMask(UnsharpMask::default())],
+                10,
+                10
+            ),
+            400 * 300 * 16 * 3
+        );
+    }
+
+    #[test]
+    fn oversized_canvases_are_refused() {
+        let limits = DecodeLimits::default();
+        let canvas = |width, height| Canvas {
+            width,
+            height,
+            ..Canvas::default()
+        };
+        assert!(check_canvas(&canvas(1000, 1000), &limits).is_ok());
+        assert!(check_canvas(&canvas(65535, 10), &limits).is_err());
+        // Within the sides but over the allocation limit as floats
+        assert!(check_canvas(&canvas(16000, 16000), &limits).is_err());
+
+        let img = DynamicImage::new_rgb8(4, 4);
+        let error = Transform::FitAndPad(canvas(65535, 65535))
+            .apply(img, &limits)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::OutOfMemory);
+    }
+}
@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct WatchState {
+    timed_out: bool,
+    // Set once the outputs are in place, the result is on its way
+    finished: bool,
+    // Outputs written so far as (temporary file, final path). They only
+    // replace anything at the final path once the conversion is done.
+    pending: Vec<(PathBuf, PathBuf)>,
+}
+
+thread_local! {
+    // Set on the worker thread of a file that runs under a time budget
+    static CURRENT: RefCell<Option<Arc<Mutex<WatchState>>>> = const { RefCell::new(None) };
+}
+
+// Runs `convert` on its own thread and gives up after `timeout`. A thread
+// can't be stopped, so a stuck conversion is left to finish in the background
+// but nothing it writes is kept.
+pub(super) fn run_with_timeout<T: Send + 'static>(
+    timeout: Option<Duration>,
+    convert: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let Some(timeout) = timeout else {
+        return convert();
+    };
+
+    let state = Arc::new(Mutex::new(WatchState::default()));
+    let (sender, receiver) = mpsc::channel();
+    {
+        let state = state.clone();
+        std::thread::spawn(move || {
+            CURRENT.with(|current| *current.borrow_mut() = Some(state.clone()));
+            let result = convert();
+            let moved = finish(&mut state.lock().unwrap());
+            // The batch may have stopped listening already
+            let _ = sender.send(result.and_then(|value| moved.map(|_| value)));
+        });
+    }
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => {
+            let mut state = state.lock().unwrap();
+            if state.finished {
+                // Done just in time, the result is already being sent
+                drop(state);
+                return receiver
+                    .recv()
+                    .unwrap_or_else(|_| Err(Error::other("Conversion stopped without a result")));
+            }
+            state.timed_out = true;
+            for (temporary, _) in state.pending.drain(..) {
+                let _ = std::fs::remove_file(temporary);
+            }
+            Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("Timed out after {} s", timeout.as_secs_f64()),
+            ))
+        }
+        Err(RecvTimeoutError::Disconnected) => {
+            Err(Error::other("Conversion stopped without a result"))
+        }
+    }
+}
+
+// Moves the outputs of a conversion that beat its deadline into place. An
+// output that can't be moved fails the file, the others are still moved.
+fn finish(state: &mut WatchState) -> Result<(), Error> {
+    if state.timed_out {
+        return Ok(());
+    }
+    state.finished = true;
+    let mut result = Ok(());
+    for (temporary, path) in state.pending.drain(..) {
+        if let Err(e) = std::fs::rename(&temporary, &path) {
+            let _ = std::fs::remove_file(temporary);
+            if result.is_ok() {
+                result = Err(Error::new(
+                    e.kind(),
+                    format!("Could not move output to {}: {}", path.display(), e),
+                ));
+            }
+        }
+    }
+    result
+}
+
+// Writes an output unless the file it belongs to has run out of time. The
+// lock makes the check and the write atomic against the watchdog's cleanup.
+pub(super) fn write_output(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    CURRENT.with(|current| match current.borrow().as_ref() {
+        Some(state) => {
+            let mut state = state.lock().unwrap();
+            if state.timed_out {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "Timed out, output discarded",
+                ));
+            }
+            let temporary = temporary_path(path);
+            std::fs::write(&temporary, contents)?;
+            state.pending.push((temporary, path.to_path_buf()));
+            Ok(())
+        }
+        None => std::fs::write(path, contents),
+    })
+}
+
+pub(super) fn timed_out() -> bool {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|state| state.lock().unwrap().timed_out)
+    })
+}
+
+// Next to the output so the final rename stays on one filesystem
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::super::{
+        convert_batch_parallel, ConvertFormat, ConvertOptions, EncoderSettings, OutputTarget,
+    };
+    use super::*;
+
+    #[test]
+    fn avif_batch_finishes_under_a_time_limit() {
+        let dir = temp_dir("avif_timeout");
+        // More files than pool workers, so every worker ends up waiting
+        let files: Vec<PathBuf> = (0..rayon::current_num_threads() + 1)
+            .map(|i| write_png(&dir, &format!("input{}.png", i), 32, 32))
+            .collect();
+        let target = OutputTarget {
+            format: ConvertFormat::Avif,
+            encoder: EncoderSettings {
+                quality: Some(60),
+                avif_speed: 10,
+            },
+        };
+        let options = ConvertOptions {
+            timeout: Some(Duration::from_secs(30)),
+            ..ConvertOptions::default()
+        };
+
+        let summary = convert_batch_parallel(files.clone(), &[target], &options, false, |_, _| {});
+        assert_eq!(summary.errors, Vec::<String>::new());
+        assert_eq!(summary.success_count, files.len());
+        for (_, output) in summary.converted.iter().flat_map(|c| &c.outputs) {
+            assert!(output.output_bytes > 0);
+        }
+        assert!(std::fs::read_dir(&dir).unwrap().all(|entry| entry
+            .unwrap()
+            .path()
+            .extension()
+            .unwrap()
+            != "part"));
+    }
+
+    #[test]
+    fn slow_conversions_time_out_and_keep_nothing() {
+        let dir = temp_dir("slow_timeout");
+        let output = dir.join("slow.png");
+        let output_clone = output.clone();
+        let result = run_with_timeout(Some(Duration::from_millis(50)), move || {
+            std::thread::sleep(Duration::from_millis(300));
+            write_output(&output_clone, b"late")
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+
+        // The late write is refused once the thread wakes up
+        std::thread::sleep(Duration::from_millis(500));
+        assert!(!output.exists());
+        assert!(!temporary_path(&output).exists());
+    }
+
+    #[test]
+    fn outputs_that_cannot_be_moved_fail_the_file() {
+        let dir = temp_dir("unmovable_output");
+        // A folder with something in it can't be replaced by a file
+        let output = dir.join("taken.png");
+        std::fs::create_dir(&output).unwrap();
+        std::fs::write(output.join("keep"), b"keep").unwrap();
+
+        let output_clone = output.clone();
+        let result = run_with_timeout(Some(Duration::from_secs(10)), move || {
+            write_output(&output_clone, b"data")
+        });
+        assert!(result.is_err());
+        assert!(output.join("keep").exists());
+        assert!(!temporary_path(&output).exists());
+    }
+
+    #[test]
+    fn outputs_appear_only_once_finished() {
+        let dir = temp_dir("finished_outputs");
+        let output = dir.join("done.png");
+        let output_clone = output.clone();
+        run_with_timeout(Some(Duration::from_secs(10)), move || {
+            write_output(&output_clone, b"done")?;
+            assert!(!output_clone.exists());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"done");
+    }
+}
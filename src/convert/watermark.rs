@@ -0,0 +1,352 @@
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::adjust::restore_color_type;
+use super::{decode_image, DecodeLimits};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WatermarkSource {
+    Logo(PathBuf),
+    Text {
+        text: String,
+        font: PathBuf,
+        color: [u8; 4],
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    Tiled,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    pub source: WatermarkSource,
+    pub anchor: Anchor,
+    // 0.0 is invisible, 1.0 keeps the source alpha
+    pub opacity: f32,
+    // Both relative to the output width, so a batch of mixed sizes looks alike
+    pub margin: f32,
+    pub scale: f32,
+}
+
+impl Default for Watermark {
+    fn default() -> Self {
+        Watermark {
+            source: WatermarkSource::Text {
+                text: String::new(),
+                font: PathBuf::new(),
+                color: [255, 255, 255, 255],
+            },
+            anchor: Anchor::BottomRight,
+            opacity: 0.5,
+            margin: 0.02,
+            scale: 0.25,
+        }
+    }
+}
+
+pub fn apply_watermark(
+    img: DynamicImage,
+    watermark: &Watermark,
+    limits: &DecodeLimits,
+) -> Result<DynamicImage, Error> {
+    let target_width =
+        ((img.width() as f32 * watermark.scale.clamp(0.0, 1.0)).round() as u32).max(1);
+    let source = load(&watermark.source, limits);
+    let overlay = match (&watermark.source, usable(&watermark.source, &source)?) {
+        (_, Loaded::Logo(logo)) => render_logo(logo, target_width),
+        (WatermarkSource::Text { text, color, .. }, Loaded::Font(font)) => {
+            render_text(text, font, *color, target_width)
+        }
+        (WatermarkSource::Logo(_), Loaded::Font(_)) => unreachable!(),
+    };
+
+    let opacity = watermark.opacity.clamp(0.0, 1.0);
+    let margin = (img.width() as f32 * watermark.margin.max(0.0)).round() as i64;
+
+    let color = img.color();
+    let mut canvas = img.into_rgba32f();
+    let (width, height) = (canvas.width() as i64, canvas.height() as i64);
+    let (logo_width, logo_height) = (overlay.width() as i64, overlay.height() as i64);
+
+    let positions = match watermark.anchor {
+        Anchor::TopLeft => vec![(margin, margin)],
+        Anchor::TopRight => vec![(width - logo_width - margin, margin)],
+        Anchor::BottomLeft => vec![(margin, height - logo_height - margin)],
+        Anchor::BottomRight => vec![(width - logo_width - margin, height - logo_height - margin)],
+        Anchor::Center => vec![((width - logo_width) / 2, (height - logo_height) / 2)],
+        Anchor::Tiled => {
+            let step_x = (logo_width + margin).max(1);
+            let step_y = (logo_height + margin).max(1);
+            let mut positions = Vec::new();
+            let mut y = margin;
+            while y < height {
+                let mut x = margin;
+                while x < width {
+                    positions.push((x, y));
+                    x += step_x;
+                }
+                y += step_y;
+            }
+            positions
+        }
+    };
+
+    for (x, y) in positions {
+        blend(&mut canvas, &overlay, x, y, opacity);
+    }
+
+    Ok(restore_color_type(canvas, color))
+}
+
+// A watermark's logo or font as read from disk, or why it couldn't be
+type Source = Result<Loaded, (ErrorKind, String)>;
+
+enum Loaded {
+    Logo(DynamicImage),
+    Font(FontVec),
+}
+
+// Whether a source is a logo, and its path
+type SourceKey = (bool, PathBuf);
+
+// Sources in use. A batch holds its sources for as long as it runs so every
+// file reuses them, and they are read again once it ends.
+static LOADED: Mutex<Vec<(SourceKey, Weak<Source>)>> = Mutex::new(Vec::new());
+
+// The sources of a batch's watermarks, loaded once up front
+pub(super) struct HeldSources {
+    _sources: Vec<Arc<Source>>,
+    // The first watermark that can't be drawn
+    pub(super) error: Option<Error>,
+}
+
+pub(super) fn hold_sources<'a>(
+    watermarks: impl IntoIterator<Item = &'a Watermark>,
+    limits: &DecodeLimits,
+) -> HeldSources {
+    let mut held = HeldSources {
+        _sources: Vec::new(),
+        error: None,
+    };
+    for watermark in watermarks {
+        let source = load(&watermark.source, limits);
+        if held.error.is_none() {
+            held.error = usable(&watermark.source, &source).err();
+        }
+        held._sources.push(source);
+    }
+    held
+}
+
+fn load(watermark: &WatermarkSource, limits: &DecodeLimits) -> Arc<Source> {
+    let path = match watermark {
+        WatermarkSource::Logo(path) => path,
+        WatermarkSource::Text { font, .. } => font,
+    };
+    let logo = matches!(watermark, WatermarkSource::Logo(_));
+
+    {
+        let mut loaded = LOADED.lock().unwrap();
+        loaded.retain(|(_, source)| source.strong_count() > 0);
+        let held = loaded
+            .iter()
+            .find(|(key, _)| key.0 == logo && key.1 == *path)
+            .and_then(|(_, source)| source.upgrade());
+        if let Some(source) = held {
+            return source;
+        }
+    }
+
+    let source = Arc::new(if logo {
+        decode_image(path, limits)
+            .map(|(logo, _)| Loaded::Logo(logo))
+            .map_err(|e| (e.kind(), format!("Watermark logo failed to load: {}", e)))
+    } else {
+        std::fs::read(path)
+            .map_err(|e| {
+                (
+                    e.kind(),
+                    format!("Watermark font {} failed to load: {}", path.display(), e),
+                )
+            })
+            .and_then(|data| {
+                FontVec::try_from_vec(data).map_err(|_| {
+                    (
+                        ErrorKind::InvalidData,
+                        format!("{} is not a valid font", path.display()),
+                    )
+                })
+            })
+            .map(Loaded::Font)
+    });
+    LOADED
+        .lock()
+        .unwrap()
+        .push(((logo, path.clone()), Arc::downgrade(&source)));
+    source
+}
+
+// The loaded logo or font, if the watermark can be drawn with it
+fn usable<'a>(watermark: &WatermarkSource, source: &'a Source) -> Result<&'a Loaded, Error> {
+    let loaded = source
+        .as_ref()
+        .map_err(|(kind, message)| Error::new(*kind, message.clone()))?;
+    if let (WatermarkSource::Text { text, .. }, Loaded::Font(font)) = (watermark, loaded) {
+        if text.trim().is_empty() {
+            return Err(Error::other("Watermark text is empty"));
+        }
+        if text_width(font, text, PxScale::from(REFERENCE_SIZE)) <= 0.0 {
+            return Err(Error::other("Watermark text has no visible glyphs"));
+        }
+    }
+    Ok(loaded)
+}
+
+fn render_logo(logo: &DynamicImage, target_width: u32) -> RgbaImage {
+    let target_height = ((logo.height() as f64 * target_width as f64 / logo.width().max(1) as f64)
+        .round() as u32)
+        .max(1);
+
+    logo.resize_exact(target_width, target_height, imageops::FilterType::Lanczos3)
+        .into_rgba8()
+}
+
+// Text is measured at this size, then scaled to span the target width
+const REFERENCE_SIZE: f32 = 100.0;
+
+fn render_text(text: &str, font: &FontVec, color: [u8; 4], target_width: u32) -> RgbaImage {
+    let measured = text_width(font, text, PxScale::from(REFERENCE_SIZE));
+    let scale = PxScale::from(REFERENCE_SIZE * target_width as f32 / measured);
+    let scaled = font.as_scaled(scale);
+
+    let width = text_width(font, text, scale).ceil() as u32;
+    let height = (scaled.ascent() - scaled.descent()).ceil() as u32;
+    let mut overlay = RgbaImage::new(width.max(1), height.max(1));
+
+    let mut caret = 0.0;
+    let mut previous = None;
+    for ch in text.chars() {
+        let id = font.glyph_id(ch);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, point(caret, scaled.ascent()));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, coverage| {
+            let px = bounds.min.x as i64 + x as i64;
+            let py = bounds.min.y as i64 + y as i64;
+            if px < 0 || py < 0 || px >= overlay.width() as i64 || py >= overlay.height() as i64 {
+                return;
+            }
+            let pixel = overlay.get_pixel_mut(px as u32, py as u32);
+            let alpha = (coverage.clamp(0.0, 1.0) * color[3] as f32).round() as u8;
+            *pixel = Rgba([color[0], color[1], color[2], pixel[3].max(alpha)]);
+        });
+    }
+
+    overlay
+}
+
+fn text_width(font: &FontVec, text: &str, scale: PxScale) -> f32 {
+    let scaled = font.as_scaled(scale);
+    let mut width = 0.0;
+    let mut previous = None;
+    for ch in text.chars() {
+        let id = font.glyph_id(ch);
+        if let Some(previous) = previous {
+            width += scaled.kern(previous, id);
+        }
+        width += scaled.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+fn blend(canvas: &mut image::Rgba32FImage, overlay: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    for (ox, oy, pixel) in overlay.enumerate_pixels() {
+        let cx = x + ox as i64;
+        let cy = y + oy as i64;
+        if cx < 0 || cy < 0 || cx >= canvas.width() as i64 || cy >= canvas.height() as i64 {
+            continue;
+        }
+
+        let alpha = pixel[3] as f32 / 255.0 * opacity;
+        if alpha <= 0.0 {
+            continue;
+        }
+
+        let target = canvas.get_pixel_mut(cx as u32, cy as u32);
+        for c in 0..3 {
+            let source = pixel[c] as f32 / 255.0;
+            target[c] = source * alpha + target[c] * (1.0 - alpha);
+        }
+        target[3] = alpha + target[3] * (1.0 - alpha);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing::{temp_dir, write_png};
+    use super::super::{convert_batch_parallel, ConvertFormat, ConvertOptions, OutputTarget};
+    use super::super::{DecodeLimits, Transform};
+    use super::*;
+
+    fn logo(path: PathBuf) -> Watermark {
+        Watermark {
+            source: WatermarkSource::Logo(path),
+            ..Watermark::default()
+        }
+    }
+
+    #[test]
+    fn a_missing_logo_fails_the_batch_once() {
+        let dir = temp_dir("watermark_missing");
+        let files = (0..3)
+            .map(|i| write_png(&dir, &format!("{}.png", i), 16, 16))
+            .collect();
+        let options = ConvertOptions {
+            transforms: vec![Transform::Watermark(logo(dir.join("missing.png")))],
+            ..ConvertOptions::default()
+        };
+        let targets = [OutputTarget::new(ConvertFormat::Bmp)];
+        let summary = convert_batch_parallel(files, &targets, &options, false, |_, _| {});
+
+        assert_eq!(summary.success_count, 0);
+        assert_eq!(summary.error_count, 3);
+        assert_eq!(summary.records.len(), 3);
+        assert_eq!(summary.errors.len(), 1);
+        assert!(summary.errors[0].starts_with("Watermark logo failed to load"));
+    }
+
+    #[test]
+    fn held_logos_are_not_read_again() {
+        let dir = temp_dir("watermark_held");
+        let watermark = logo(write_png(&dir, "logo.png", 8, 8));
+        let limits = DecodeLimits::default();
+        let img = || DynamicImage::new_rgba8(32, 32);
+
+        let held = hold_sources([&watermark], &limits);
+        assert!(held.error.is_none());
+        std::fs::remove_file(dir.join("logo.png")).unwrap();
+        assert!(apply_watermark(img(), &watermark, &limits).is_ok());
+
+        drop(held);
+        assert!(apply_watermark(img(), &watermark, &limits).is_err());
+    }
+}
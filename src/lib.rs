@@ -0,0 +1,3 @@
+// The conversion engine, usable without the GUI by turning off default
+// features
+pub mod convert;
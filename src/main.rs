@@ -1,10 +1,32 @@
-pub mod convert;
+mod cli;
+#[cfg(feature = "gui")]
 mod window;
 
+use image_convert::convert;
+
+#[cfg(feature = "gui")]
 use fltk::{app, prelude::*};
+#[cfg(feature = "gui")]
 use window::create_app;
 
 fn main() {
+    // Any arguments run the command line converter instead of the GUI
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args));
+    }
+
+    run_gui();
+}
+
+// Built without the GUI there is nothing to start, so print the usage
+#[cfg(not(feature = "gui"))]
+fn run_gui() {
+    std::process::exit(cli::run(&[]));
+}
+
+#[cfg(feature = "gui")]
+fn run_gui() {
     println!("Initializing FLTK application...");
 
     // Initialize FLTK
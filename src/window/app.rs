@@ -4,9 +4,18 @@ use fltk::{
 use std::cell::RefCell;
 use std::path::PathBuf;
 use std::rc::Rc;
-
-use crate::convert::{convert_batch_parallel, convert_image, ConvertFormat};
-use crate::window::dialog;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+
+use crate::convert::{
+    convert_batch_auto, convert_batch_parallel, convert_batch_rules, convert_image,
+    convert_image_auto, convert_responsive_batch, extension_mismatch,
+    find_duplicates_with_thumbnails, fix_extension, inspect, output_path_for, preview_rules,
+    scan_files, BatchSummary, ConvertFormat, ConvertOptions, ConvertStats, DecodeLimits,
+    EncoderSettings, FormatRule, OutputTarget, ProgressEvent, Transform, DEFAULT_MAX_DISTANCE,
+};
+use crate::window::options::BatchExtras;
+use crate::window::{dialog, duplicates, info, options};
 
 pub fn create_app() -> Window {
     let mut wind = Window::new(100, 100, 800, 700, "Image Converter");
@@ -16,6 +25,11 @@ pub fn create_app() -> Window {
     // State management
     let single_file: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
     let batch_files: Rc<RefCell<Vec<PathBuf>>> = Rc::new(RefCell::new(Vec::new()));
+    let single_options: Rc<RefCell<ConvertOptions>> =
+        Rc::new(RefCell::new(ConvertOptions::default()));
+    let batch_options: Rc<RefCell<ConvertOptions>> =
+        Rc::new(RefCell::new(ConvertOptions::default()));
+    let batch_extras: Rc<RefCell<BatchExtras>> = Rc::new(RefCell::new(BatchExtras::default()));
 
     // Create main vertical pack
     let mut main_pack = Pack::new(20, 20, 760, 660, "");
@@ -34,10 +48,16 @@ pub fn create_app() -> Window {
     title.set_color(Color::from_rgb(26, 26, 26));
 
     // Single file conversion section
-    create_single_upload_section(&mut main_pack, &single_file, &wind);
+    create_single_upload_section(&mut main_pack, &single_file, &single_options, &wind);
 
     // Batch file conversion section
-    create_batch_upload_section(&mut main_pack, &batch_files, &wind);
+    create_batch_upload_section(
+        &mut main_pack,
+        &batch_files,
+        &batch_options,
+        &batch_extras,
+        &wind,
+    );
 
     main_pack.end();
     wind.end();
@@ -48,6 +68,7 @@ pub fn create_app() -> Window {
 fn create_single_upload_section(
     parent: &mut Pack,
     single_file: &Rc<RefCell<Option<PathBuf>>>,
+    convert_options: &Rc<RefCell<ConvertOptions>>,
     parent_window: &Window,
 ) {
     let mut section = Group::new(0, 0, 760, 200, "");
@@ -66,10 +87,14 @@ fn create_single_upload_section(
     let mut select_btn = Button::new(20, 45, 120, 30, "Select Image");
     style_primary_button(&mut select_btn);
 
-    let mut file_label = Frame::new(150, 45, 450, 30, "No file selected");
+    let mut file_label = Frame::new(150, 45, 350, 30, "No file selected");
     file_label.set_label_color(Color::from_rgb(139, 148, 158));
     file_label.set_align(Align::Left | Align::Inside);
 
+    let mut info_btn = Button::new(510, 45, 100, 30, "Info...");
+    style_secondary_button(&mut info_btn);
+    info_btn.deactivate();
+
     let mut clear_btn = Button::new(620, 45, 100, 30, "Clear");
     style_destructive_button(&mut clear_btn);
 
@@ -78,32 +103,42 @@ fn create_single_upload_section(
     format_label.set_label_color(Color::White);
     format_label.set_align(Align::Left | Align::Inside);
 
-    let mut format_choice = Choice::new(150, 90, 100, 30, "");
+    let mut format_choice = Choice::new(150, 90, 110, 30, "");
     format_choice.add_choice("JPEG");
     format_choice.add_choice("PNG");
     format_choice.add_choice("WebP");
     format_choice.add_choice("BMP");
     format_choice.add_choice("GIF");
+    format_choice.add_choice("AVIF");
+    format_choice.add_choice("Auto (smallest)");
     format_choice.set_value(0);
     style_choice_widget(&mut format_choice);
 
     let mut overwrite_check = CheckButton::new(270, 90, 200, 30, "Overwrite existing files");
     style_checkbox(&mut overwrite_check);
 
+    let mut options_btn = Button::new(500, 90, 100, 30, "Options...");
+    style_secondary_button(&mut options_btn);
+
     let mut convert_btn = Button::new(620, 90, 100, 30, "Convert");
     style_primary_button(&mut convert_btn);
     convert_btn.deactivate();
 
     // Progress info
-    let mut progress_label = Frame::new(20, 130, 700, 25, "");
+    let mut progress_label = Frame::new(20, 170, 700, 25, "");
     progress_label.set_label_color(Color::from_rgb(139, 148, 158));
     progress_label.set_align(Align::Left | Align::Inside);
 
+    // Transform row
+    create_transform_row(130, convert_options, &progress_label);
+
     // Setup callbacks
     {
         let single_file_clone = single_file.clone();
+        let convert_options_clone = convert_options.clone();
         let parent_clone = parent_window.clone();
         let mut file_label_clone = file_label.clone();
+        let mut info_btn_clone = info_btn.clone();
         let mut convert_btn_clone = convert_btn.clone();
 
         select_btn.set_callback(move |_| {
@@ -113,88 +148,158 @@ fn create_single_upload_section(
                     .file_name()
                     .and_then(|name| name.to_str())
                     .unwrap_or("Unknown file");
-                file_label_clone.set_label(&format!("Selected: {}", filename));
+                let limits = convert_options_clone.borrow().limits.clone();
+                match inspect(&path, &limits) {
+                    Ok(image_info) => {
+                        file_label_clone.set_label(&format!(
+                            "{} ({})",
+                            filename,
+                            info::info_summary(&image_info)
+                        ));
+                        file_label_clone.set_tooltip(&info::info_summary(&image_info));
+                        info_btn_clone.activate();
+                    }
+                    Err(_) => {
+                        file_label_clone.set_label(&format!("Selected: {}", filename));
+                        info_btn_clone.deactivate();
+                    }
+                }
                 convert_btn_clone.activate();
                 app::redraw();
             }
         });
     }
 
+    {
+        let single_file_clone = single_file.clone();
+        let convert_options_clone = convert_options.clone();
+        let parent_clone = parent_window.clone();
+
+        info_btn.set_callback(move |_| {
+            let Some(path) = single_file_clone.borrow().clone() else {
+                return;
+            };
+            let limits = convert_options_clone.borrow().limits.clone();
+            match inspect(&path, &limits) {
+                Ok(image_info) => info::open_info_dialog(&image_info),
+                Err(e) => dialog::show_error_dialog(
+                    &parent_clone,
+                    &format!("Could not inspect {}: {}", path.display(), e),
+                ),
+            }
+        });
+    }
+
     {
         let single_file_clone = single_file.clone();
         let mut file_label_clone = file_label.clone();
+        let mut info_btn_clone = info_btn.clone();
         let mut convert_btn_clone = convert_btn.clone();
 
         clear_btn.set_callback(move |_| {
             *single_file_clone.borrow_mut() = None;
             file_label_clone.set_label("No file selected");
+            file_label_clone.set_tooltip("");
+            info_btn_clone.deactivate();
             convert_btn_clone.deactivate();
             app::redraw();
         });
     }
 
+    {
+        let convert_options_clone = convert_options.clone();
+        let mut progress_label_clone = progress_label.clone();
+
+        options_btn.set_callback(move |_| {
+            options::open_options_dialog("Single File Options", &convert_options_clone, None);
+
+            // The dialog may have added or removed transforms
+            let transforms = &convert_options_clone.borrow().transforms;
+            if transforms.is_empty() {
+                progress_label_clone.set_label("");
+            } else {
+                progress_label_clone.set_label(&transform_summary(transforms));
+            }
+            app::redraw();
+        });
+    }
+
     {
         let single_file_clone = single_file.clone();
+        let convert_options_clone = convert_options.clone();
         let format_choice_clone = format_choice.clone();
         let overwrite_check_clone = overwrite_check.clone();
+        let mut file_label_clone = file_label.clone();
         let mut progress_label_clone = progress_label.clone();
         let parent_clone = parent_window.clone();
 
         convert_btn.set_callback(move |_| {
-            let single_file = single_file_clone.borrow();
+            // Cloned so a fixed extension can update the selection
+            let single_file = single_file_clone.borrow().clone();
             if let Some(input_path) = single_file.as_ref() {
-                let selected_index = format_choice_clone.value();
-                let format = match selected_index {
-                    0 => ConvertFormat::Jpeg,
-                    1 => ConvertFormat::Png,
-                    2 => ConvertFormat::Webp,
-                    3 => ConvertFormat::Bmp,
-                    4 => ConvertFormat::Gif,
-                    _ => ConvertFormat::Jpeg,
-                };
-
-                let input_stem = input_path
-                    .file_stem()
-                    .and_then(|s| s.to_str())
-                    .unwrap_or("converted");
-
+                let format = format_from_index(format_choice_clone.value());
                 let overwrite = overwrite_check_clone.is_checked();
-                let output_filename = if overwrite {
-                    format!("{}.{}", input_stem, format.extension())
-                } else {
-                    let mut counter = 1;
-                    let base_path = input_path.parent().unwrap_or(std::path::Path::new("."));
-                    loop {
-                        let filename = format!(
-                            "{}_converted_{}.{}",
-                            input_stem,
-                            counter,
-                            format.extension()
-                        );
-                        let test_path = base_path.join(&filename);
-                        if !test_path.exists() {
-                            break filename;
-                        }
-                        counter += 1;
-                    }
-                };
-
-                let output_path = input_path
-                    .parent()
-                    .unwrap_or(std::path::Path::new("."))
-                    .join(output_filename);
 
                 progress_label_clone.set_label("Converting...");
                 app::redraw();
 
-                match convert_image(input_path.clone(), output_path.clone(), format) {
-                    Ok(_) => {
-                        progress_label_clone.set_label("Conversion completed successfully!");
-                        let message = format!(
+                let options = convert_options_clone.borrow().clone();
+                let result = match &format {
+                    Some(format) => {
+                        let output_path = output_path_for(input_path, format, overwrite);
+                        convert_image(
+                            input_path.clone(),
+                            output_path.clone(),
+                            format.clone(),
+                            &options,
+                        )
+                        .map(|stats| (output_path, stats))
+                    }
+                    None => convert_image_auto(input_path.clone(), &options, overwrite),
+                };
+                match result {
+                    Ok((output_path, stats)) => {
+                        let mut message = format!(
                             "Successfully converted image to:\n{}",
                             output_path.display()
                         );
-                        dialog::show_info_dialog(&parent_clone, &message);
+                        if format.is_none() {
+                            message.push_str(&format!("\n\n{} won", stats.format.name()));
+                        }
+                        if options.size_target.is_some()
+                            || format.is_none()
+                            || options.verify.is_some()
+                        {
+                            message.push_str(&format!("\n\n{}", describe_stats(&stats)));
+                        }
+
+                        if let Some(mut mismatch) = extension_mismatch(input_path) {
+                            if options.fix_extension {
+                                if let Err(e) = fix_extension(input_path, &mut mismatch) {
+                                    message.push_str(&format!(
+                                        "\n\nCould not fix the extension: {}",
+                                        e
+                                    ));
+                                }
+                            }
+                            message.push_str(&format!("\n\nThe input {}.", mismatch.describe()));
+                            if let Some(renamed) = mismatch.renamed_to {
+                                file_label_clone.set_label(&format!(
+                                    "Selected: {}",
+                                    renamed.file_name().unwrap_or_default().to_string_lossy()
+                                ));
+                                *single_file_clone.borrow_mut() = Some(renamed);
+                            }
+                        }
+
+                        if is_below_threshold(&stats, &options) {
+                            progress_label_clone.set_label("Converted, below quality threshold");
+                            message.push_str("\n\nQuality is below the verify threshold.");
+                            dialog::show_error_dialog(&parent_clone, &message);
+                        } else {
+                            progress_label_clone.set_label("Conversion completed successfully!");
+                            dialog::show_info_dialog(&parent_clone, &message);
+                        }
                     }
                     Err(e) => {
                         progress_label_clone.set_label("Conversion failed!");
@@ -214,6 +319,8 @@ fn create_single_upload_section(
 fn create_batch_upload_section(
     parent: &mut Pack,
     batch_files: &Rc<RefCell<Vec<PathBuf>>>,
+    convert_options: &Rc<RefCell<ConvertOptions>>,
+    batch_extras: &Rc<RefCell<BatchExtras>>,
     parent_window: &Window,
 ) {
     let mut section = Group::new(0, 0, 760, 400, "");
@@ -232,6 +339,12 @@ fn create_batch_upload_section(
     let mut select_btn = Button::new(20, 45, 150, 30, "Select Multiple Images");
     style_primary_button(&mut select_btn);
 
+    let mut scan_btn = Button::new(340, 45, 130, 30, "Verify Files");
+    style_secondary_button(&mut scan_btn);
+
+    let mut duplicates_btn = Button::new(480, 45, 130, 30, "Find Duplicates");
+    style_secondary_button(&mut duplicates_btn);
+
     let mut clear_btn = Button::new(620, 45, 100, 30, "Clear All");
     style_destructive_button(&mut clear_btn);
 
@@ -250,27 +363,42 @@ fn create_batch_upload_section(
     format_label.set_label_color(Color::White);
     format_label.set_align(Align::Left | Align::Inside);
 
-    let mut format_choice = Choice::new(150, 265, 100, 30, "");
+    let mut format_choice = Choice::new(150, 265, 110, 30, "");
     format_choice.add_choice("JPEG");
     format_choice.add_choice("PNG");
     format_choice.add_choice("WebP");
     format_choice.add_choice("BMP");
     format_choice.add_choice("GIF");
+    format_choice.add_choice("AVIF");
+    format_choice.add_choice("Auto (smallest)");
     format_choice.set_value(0);
     style_choice_widget(&mut format_choice);
 
     let mut overwrite_check = CheckButton::new(270, 265, 200, 30, "Overwrite existing files");
     style_checkbox(&mut overwrite_check);
 
+    let mut options_btn = Button::new(500, 265, 100, 30, "Options...");
+    style_secondary_button(&mut options_btn);
+
     let mut process_btn = Button::new(620, 265, 100, 30, "Convert All");
     style_primary_button(&mut process_btn);
     process_btn.deactivate();
 
     // Progress info
-    let mut progress_label = Frame::new(20, 305, 700, 25, "");
+    let mut progress_label = Frame::new(20, 345, 590, 25, "");
     progress_label.set_label_color(Color::from_rgb(139, 148, 158));
     progress_label.set_align(Align::Left | Align::Inside);
 
+    let mut export_btn = Button::new(620, 345, 100, 25, "Export Report");
+    style_secondary_button(&mut export_btn);
+    export_btn.deactivate();
+
+    // Transform row
+    create_transform_row(305, convert_options, &progress_label);
+
+    // Kept around so the report can be exported after the summary is closed
+    let last_summary: Rc<RefCell<Option<BatchSummary>>> = Rc::new(RefCell::new(None));
+
     // Setup callbacks
     {
         let batch_files_clone = batch_files.clone();
@@ -305,26 +433,240 @@ fn create_batch_upload_section(
 
     {
         let batch_files_clone = batch_files.clone();
+        let convert_options_clone = convert_options.clone();
+        let parent_clone = parent_window.clone();
+        let mut progress_label_clone = progress_label.clone();
+
+        scan_btn.set_callback(move |btn| {
+            let files = batch_files_clone.borrow().clone();
+            if files.is_empty() {
+                return;
+            }
+
+            btn.deactivate();
+            progress_label_clone.set_label("Verifying files...");
+            let total = files.len();
+            let limits = convert_options_clone.borrow().limits.clone();
+            let processed = Arc::new(AtomicUsize::new(0));
+            let processed_clone = processed.clone();
+            let results = run_in_background(
+                move || {
+                    scan_files(&files, &limits, move |done, _total| {
+                        processed_clone.store(done, Ordering::Relaxed);
+                    })
+                },
+                || {
+                    progress_label_clone.set_label(&format!(
+                        "Verifying {} of {} files...",
+                        processed.load(Ordering::Relaxed),
+                        total
+                    ));
+                },
+            );
+            btn.activate();
+            let Ok(results) = results else {
+                progress_label_clone.set_label("");
+                dialog::show_error_dialog(&parent_clone, "Verifying the files failed.");
+                return;
+            };
+
+            let problems: Vec<String> = results
+                .iter()
+                .filter(|result| !result.is_ok())
+                .map(|result| {
+                    let name = result
+                        .path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy();
+                    format!("{}: {}", name, result.describe())
+                })
+                .collect();
+            progress_label_clone.set_label(&format!(
+                "Verified {} files, {} with problems",
+                results.len(),
+                problems.len()
+            ));
+
+            if problems.is_empty() {
+                dialog::show_info_dialog(
+                    &parent_clone,
+                    &format!("All {} files decoded cleanly.", results.len()),
+                );
+            } else {
+                dialog::show_error_dialog(
+                    &parent_clone,
+                    &format!(
+                        "{} of {} files have problems:\n\n{}",
+                        problems.len(),
+                        results.len(),
+                        problems.join("\n")
+                    ),
+                );
+            }
+            app::redraw();
+        });
+    }
+
+    {
+        let batch_files_clone = batch_files.clone();
+        let convert_options_clone = convert_options.clone();
+        let parent_clone = parent_window.clone();
+        let mut file_browser_clone = file_browser.clone();
+        let mut process_btn_clone = process_btn.clone();
+        let mut progress_label_clone = progress_label.clone();
+
+        duplicates_btn.set_callback(move |btn| {
+            let files = batch_files_clone.borrow().clone();
+            if files.len() < 2 {
+                return;
+            }
+
+            btn.deactivate();
+            progress_label_clone.set_label("Looking for duplicates...");
+            let limits = convert_options_clone.borrow().limits.clone();
+            let groups = run_in_background(
+                move || {
+                    find_duplicates_with_thumbnails(
+                        &files,
+                        DEFAULT_MAX_DISTANCE,
+                        duplicates::THUMB_SIZE,
+                        &limits,
+                    )
+                },
+                || {},
+            );
+            btn.activate();
+            progress_label_clone.set_label("");
+            let Ok(groups) = groups else {
+                dialog::show_error_dialog(&parent_clone, "Looking for duplicates failed.");
+                return;
+            };
+
+            if groups.is_empty() {
+                dialog::show_info_dialog(&parent_clone, "No duplicate images found.");
+                return;
+            }
+
+            if let Some(dropped) = duplicates::open_duplicates_dialog(&groups) {
+                batch_files_clone
+                    .borrow_mut()
+                    .retain(|path| !dropped.contains(path));
+                update_file_list(&mut file_browser_clone, &batch_files_clone.borrow());
+                if batch_files_clone.borrow().is_empty() {
+                    process_btn_clone.deactivate();
+                }
+                progress_label_clone.set_label(&format!(
+                    "Removed {} duplicate(s) from the batch",
+                    dropped.len()
+                ));
+            }
+            app::redraw();
+        });
+    }
+
+    {
+        let convert_options_clone = convert_options.clone();
+        let batch_extras_clone = batch_extras.clone();
+        let mut progress_label_clone = progress_label.clone();
+
+        options_btn.set_callback(move |_| {
+            options::open_options_dialog(
+                "Batch Options",
+                &convert_options_clone,
+                Some(&batch_extras_clone),
+            );
+
+            // The dialog may have added or removed transforms
+            let transforms = &convert_options_clone.borrow().transforms;
+            if transforms.is_empty() {
+                progress_label_clone.set_label("");
+            } else {
+                progress_label_clone.set_label(&transform_summary(transforms));
+            }
+            app::redraw();
+        });
+    }
+
+    {
+        let batch_files_clone = batch_files.clone();
+        let convert_options_clone = convert_options.clone();
+        let batch_extras_clone = batch_extras.clone();
         let format_choice_clone = format_choice.clone();
         let overwrite_check_clone = overwrite_check.clone();
         let mut progress_label_clone = progress_label.clone();
         let mut process_btn_clone = process_btn.clone();
+        let mut export_btn_clone = export_btn.clone();
+        let last_summary_clone = last_summary.clone();
         let parent_clone = parent_window.clone();
 
         process_btn.set_callback(move |_| {
             let files = batch_files_clone.borrow().clone();
             if !files.is_empty() {
-                let selected_index = format_choice_clone.value();
-                let format = match selected_index {
-                    0 => ConvertFormat::Jpeg,
-                    1 => ConvertFormat::Png,
-                    2 => ConvertFormat::Webp,
-                    3 => ConvertFormat::Bmp,
-                    4 => ConvertFormat::Gif,
-                    _ => ConvertFormat::Jpeg,
-                };
+                let format = format_from_index(format_choice_clone.value());
 
                 let overwrite = overwrite_check_clone.is_checked();
+                let options = convert_options_clone.borrow().clone();
+
+                // The selected format uses the Output tab settings, extra
+                // formats bring their own quality and share the AVIF speed.
+                // Auto mode picks one format per file and ignores both.
+                let extras = batch_extras_clone.borrow().clone();
+                let mut targets = Vec::new();
+                if let Some(format) = &format {
+                    targets.push(OutputTarget {
+                        format: format.clone(),
+                        encoder: options.encoder.clone(),
+                    });
+                    for extra in &extras.extra_targets {
+                        if targets.iter().all(|t| t.format != extra.format) {
+                            targets.push(OutputTarget {
+                                format: extra.format.clone(),
+                                encoder: EncoderSettings {
+                                    avif_speed: options.encoder.avif_speed,
+                                    ..extra.encoder.clone()
+                                },
+                            });
+                        }
+                    }
+                }
+                let format_list = if format.is_some() && !extras.rules.is_empty() {
+                    "the formats picked by rules".to_string()
+                } else if format.is_some() {
+                    let format_names: Vec<&str> = targets.iter().map(|t| t.format.name()).collect();
+                    format_names.join(", ")
+                } else {
+                    "their smallest format".to_string()
+                };
+                // Rules replace the extra formats and responsive sets
+                let use_rules = format.is_some() && !extras.rules.is_empty();
+                let responsive = extras
+                    .responsive
+                    .as_ref()
+                    .filter(|_| format.is_some() && !use_rules);
+
+                // Dry run first so it is clear which rule each file falls under
+                if use_rules {
+                    process_btn_clone.deactivate();
+                    progress_label_clone.set_label("Checking which rules apply...");
+                    let files = files.clone();
+                    let rules = extras.rules.clone();
+                    let fallback = targets[0].format.clone();
+                    let limits = options.limits.clone();
+                    let preview = run_in_background(
+                        move || rule_preview(&files, &rules, &fallback, &limits),
+                        || {},
+                    );
+                    process_btn_clone.activate();
+                    progress_label_clone.set_label("");
+                    let Ok(preview) = preview else {
+                        dialog::show_error_dialog(&parent_clone, "Checking the rules failed.");
+                        return;
+                    };
+                    if !dialog::show_confirm_dialog(&parent_clone, &preview, "Convert") {
+                        return;
+                    }
+                }
 
                 // Show progress and disable button
                 process_btn_clone.deactivate();
@@ -332,45 +674,176 @@ fn create_batch_upload_section(
                 progress_label_clone.set_label("Starting conversion...");
                 app::redraw();
 
-                // Run conversion with parallel processing
-                let (success_count, error_count, errors) = convert_batch_parallel(
-                    files,
-                    format.clone(),
-                    overwrite,
-                    |_processed, _total| {
-                        // Progress callback - simplified for FLTK
-                    },
-                );
+                // Convert on a worker thread and keep the window responsive,
+                // showing which file is being worked on
+                let total = files.len();
+                let (sender, receiver) = mpsc::channel();
+                let work = {
+                    let mut options = options.clone();
+                    options.events = Some(sender);
+                    let auto = format.is_none();
+                    let rules = extras.rules.clone();
+                    let targets = targets.clone();
+                    let set = responsive.cloned();
+                    move || match set {
+                        _ if auto => convert_batch_auto(files, &options, overwrite, |_, _| {}),
+                        _ if use_rules => convert_batch_rules(
+                            files,
+                            &rules,
+                            &targets[0],
+                            &options,
+                            overwrite,
+                            |_, _| {},
+                        ),
+                        Some(set) => convert_responsive_batch(
+                            files,
+                            &targets,
+                            &set,
+                            &options,
+                            overwrite,
+                            |_, _| {},
+                        ),
+                        None => {
+                            convert_batch_parallel(files, &targets, &options, overwrite, |_, _| {})
+                        }
+                    }
+                };
 
-                let format_name = match format {
-                    ConvertFormat::Jpeg => "JPEG",
-                    ConvertFormat::Png => "PNG",
-                    ConvertFormat::Webp => "WebP",
-                    ConvertFormat::Bmp => "BMP",
-                    ConvertFormat::Gif => "GIF",
+                let mut started = 0;
+                let mut failed = 0;
+                let result = run_in_background(work, || {
+                    for event in receiver.try_iter() {
+                        match event {
+                            ProgressEvent::Started { file } => {
+                                started += 1;
+                                let name = file.file_name().unwrap_or_default().to_string_lossy();
+                                let mut label =
+                                    format!("Converting {} of {}: {}", started, total, name);
+                                if failed > 0 {
+                                    label.push_str(&format!(" ({} failed)", failed));
+                                }
+                                progress_label_clone.set_label(&label);
+                            }
+                            ProgressEvent::Failed { .. } => failed += 1,
+                            _ => {}
+                        }
+                    }
+                });
+                let summary = match result {
+                    Ok(summary) => summary,
+                    Err(_) => BatchSummary {
+                        error_count: total,
+                        errors: vec!["The conversion stopped unexpectedly".to_string()],
+                        ..BatchSummary::default()
+                    },
                 };
 
-                let message = if error_count == 0 {
+                let mut message = if summary.error_count == 0 && responsive.is_some() {
+                    format!(
+                        "Generated responsive sets for {} files ({} images in {})",
+                        summary.converted.len(),
+                        summary.success_count,
+                        format_list
+                    )
+                } else if summary.error_count == 0 {
                     format!(
                         "Successfully converted {} files to {}",
-                        success_count, format_name
+                        summary.converted.len(),
+                        format_list
                     )
                 } else {
-                    let error_list = errors.join("\n");
+                    let error_list = summary.errors.join("\n");
                     format!(
                         "Conversion completed:\n{} successful\n{} failed\n\nErrors:\n{}",
-                        success_count, error_count, error_list
+                        summary.success_count, summary.error_count, error_list
                     )
                 };
 
-                if error_count == 0 {
+                // Size targets pick a quality per output, and with several
+                // formats the sizes are worth comparing side by side, as are
+                // the picks of auto mode and rules and verify results.
+                // Responsive sets have their manifest for that.
+                let show_sizes = (options.size_target.is_some()
+                    || options.verify.is_some()
+                    || targets.len() != 1
+                    || use_rules)
+                    && responsive.is_none();
+                if show_sizes && !summary.converted.is_empty() {
+                    let details: Vec<String> = summary
+                        .converted
+                        .iter()
+                        .map(|file| {
+                            let outputs: Vec<String> = file
+                                .outputs
+                                .iter()
+                                .map(|(_, stats)| {
+                                    format!("{} {}", stats.format.name(), describe_stats(stats))
+                                })
+                                .collect();
+                            let name = file.input.file_name().unwrap_or_default().to_string_lossy();
+                            match &file.rule {
+                                Some(rule) => {
+                                    format!("{} [{}]: {}", name, rule, outputs.join("; "))
+                                }
+                                None => format!("{}: {}", name, outputs.join("; ")),
+                            }
+                        })
+                        .collect();
+                    message.push_str(&format!("\n\nOutput sizes:\n{}", details.join("\n")));
+                }
+
+                let flagged: Vec<String> = summary
+                    .converted
+                    .iter()
+                    .flat_map(|file| &file.outputs)
+                    .filter(|(_, stats)| is_below_threshold(stats, &options))
+                    .map(|(path, _)| {
+                        path.file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into_owned()
+                    })
+                    .collect();
+                if !flagged.is_empty() {
+                    message.push_str(&format!(
+                        "\n\nBelow quality threshold:\n{}",
+                        flagged.join("\n")
+                    ));
+                }
+
+                let mismatched: Vec<String> = summary
+                    .converted
+                    .iter()
+                    .filter_map(|file| {
+                        let mismatch = file.extension_mismatch.as_ref()?;
+                        let name = file.input.file_name().unwrap_or_default().to_string_lossy();
+                        Some(format!("{} {}", name, mismatch.describe()))
+                    })
+                    .collect();
+                if !mismatched.is_empty() {
+                    message.push_str(&format!(
+                        "\n\nExtension does not match content:\n{}",
+                        mismatched.join("\n")
+                    ));
+                }
+
+                if summary.error_count == 0 && flagged.is_empty() {
                     dialog::show_info_dialog(&parent_clone, &message);
                     progress_label_clone.set_label("All conversions completed successfully!");
+                } else if summary.error_count == 0 {
+                    dialog::show_error_dialog(&parent_clone, &message);
+                    progress_label_clone.set_label(&format!(
+                        "Conversion completed, {} below quality threshold",
+                        flagged.len()
+                    ));
                 } else {
                     dialog::show_error_dialog(&parent_clone, &message);
                     progress_label_clone.set_label("Conversion completed with errors");
                 }
 
+                *last_summary_clone.borrow_mut() = Some(summary);
+                export_btn_clone.activate();
+
                 // Reset UI
                 process_btn_clone.activate();
                 process_btn_clone.set_label("Convert All");
@@ -379,10 +852,149 @@ fn create_batch_upload_section(
         });
     }
 
+    {
+        let last_summary_clone = last_summary.clone();
+        let parent_clone = parent_window.clone();
+
+        export_btn.set_callback(move |_| {
+            let last_summary = last_summary_clone.borrow();
+            let Some(summary) = last_summary.as_ref() else {
+                return;
+            };
+            if let Some(path) = dialog::save_file_dialog("Export Report", "Reports\t*.{csv,json}") {
+                match summary.write_report(&path) {
+                    Ok(()) => dialog::show_info_dialog(
+                        &parent_clone,
+                        &format!("Report saved to:\n{}", path.display()),
+                    ),
+                    Err(e) => dialog::show_error_dialog(
+                        &parent_clone,
+                        &format!("Could not save report: {}", e),
+                    ),
+                }
+            }
+        });
+    }
+
     section.end();
     parent.add(&section);
 }
 
+// The last entry of the format choice picks the format automatically
+fn format_from_index(index: i32) -> Option<ConvertFormat> {
+    match index {
+        0 => Some(ConvertFormat::Jpeg),
+        1 => Some(ConvertFormat::Png),
+        2 => Some(ConvertFormat::Webp),
+        3 => Some(ConvertFormat::Bmp),
+        4 => Some(ConvertFormat::Gif),
+        5 => Some(ConvertFormat::Avif),
+        6 => None,
+        _ => Some(ConvertFormat::Jpeg),
+    }
+}
+
+fn create_transform_row(
+    y: i32,
+    convert_options: &Rc<RefCell<ConvertOptions>>,
+    progress_label: &Frame,
+) {
+    let mut transform_label = Frame::new(20, y, 120, 30, "Transform:");
+    transform_label.set_label_color(Color::White);
+    transform_label.set_align(Align::Left | Align::Inside);
+
+    let buttons = [
+        (150, 90, "Rotate Left", Transform::Rotate270),
+        (245, 90, "Rotate Right", Transform::Rotate90),
+        (340, 90, "Rotate 180", Transform::Rotate180),
+        (435, 80, "Flip H", Transform::FlipHorizontal),
+        (520, 80, "Flip V", Transform::FlipVertical),
+    ];
+
+    for (x, width, label, transform) in buttons {
+        let mut btn = Button::new(x, y, width, 30, label);
+        style_secondary_button(&mut btn);
+
+        let convert_options_clone = convert_options.clone();
+        let mut progress_label_clone = progress_label.clone();
+
+        btn.set_callback(move |_| {
+            let mut options = convert_options_clone.borrow_mut();
+            options.transforms.push(transform.clone());
+            progress_label_clone.set_label(&transform_summary(&options.transforms));
+            app::redraw();
+        });
+    }
+
+    let mut reset_btn = Button::new(620, y, 100, 30, "Reset");
+    style_destructive_button(&mut reset_btn);
+
+    let convert_options_clone = convert_options.clone();
+    let mut progress_label_clone = progress_label.clone();
+
+    reset_btn.set_callback(move |_| {
+        convert_options_clone.borrow_mut().transforms.clear();
+        progress_label_clone.set_label("");
+        app::redraw();
+    });
+}
+
+fn transform_summary(transforms: &[Transform]) -> String {
+    let names: Vec<&str> = transforms.iter().map(|t| t.name()).collect();
+    format!("Transforms: {}", names.join(", "))
+}
+
+fn rule_preview(
+    files: &[PathBuf],
+    rules: &[FormatRule],
+    fallback: &ConvertFormat,
+    limits: &DecodeLimits,
+) -> String {
+    let lines: Vec<String> = preview_rules(files, rules, limits)
+        .into_iter()
+        .map(|(file, result)| {
+            let name = file
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            match result {
+                Ok(Some(index)) => format!("{}: {}", name, rules[index].describe()),
+                Ok(None) => format!("{}: no rule matched → {}", name, fallback.name()),
+                Err(e) => format!("{}: {}", name, e),
+            }
+        })
+        .collect();
+    format!("Dry run:\n{}\n\nConvert these files?", lines.join("\n"))
+}
+
+fn describe_stats(stats: &ConvertStats) -> String {
+    let size = format!("{:.1} KB", stats.output_bytes as f64 / 1024.0);
+    let description = match stats.quality {
+        Some(quality) => format!(
+            "quality {}, {}x{}, {}",
+            quality, stats.width, stats.height, size
+        ),
+        None => format!("{}x{}, {}", stats.width, stats.height, size),
+    };
+    match &stats.metrics {
+        Some(metrics) if metrics.psnr.is_infinite() => format!("{}, lossless", description),
+        Some(metrics) => format!(
+            "{}, PSNR {:.1} dB, SSIM {:.3}, max delta {}",
+            description, metrics.psnr, metrics.ssim, metrics.max_delta
+        ),
+        None => description,
+    }
+}
+
+// Outputs that could not be decoded again are not flagged
+fn is_below_threshold(stats: &ConvertStats, options: &ConvertOptions) -> bool {
+    match (&options.verify, &stats.metrics) {
+        (Some(threshold), Some(metrics)) => metrics.is_below(threshold),
+        _ => false,
+    }
+}
+
 fn update_file_list(browser: &mut Browser, files: &[PathBuf]) {
     browser.clear();
 
@@ -398,7 +1010,22 @@ fn update_file_list(browser: &mut Browser, files: &[PathBuf]) {
     app::redraw();
 }
 
-fn style_primary_button(btn: &mut Button) {
+// Runs `work` off the UI thread, handling events and calling `tick` until it
+// is done so the window keeps redrawing
+fn run_in_background<T: Send + 'static>(
+    work: impl FnOnce() -> T + Send + 'static,
+    mut tick: impl FnMut(),
+) -> std::thread::Result<T> {
+    let worker = std::thread::spawn(work);
+    while !worker.is_finished() {
+        let _ = app::wait_for(0.05);
+        tick();
+        app::redraw();
+    }
+    worker.join()
+}
+
+pub(super) fn style_primary_button(btn: &mut Button) {
     btn.set_color(Color::from_rgb(13, 110, 253));
     btn.set_selection_color(Color::from_rgb(10, 88, 202));
     btn.set_label_color(Color::White);
@@ -410,7 +1037,7 @@ fn style_primary_button(btn: &mut Button) {
     btn.clear_visible_focus();
 }
 
-fn style_destructive_button(btn: &mut Button) {
+pub(super) fn style_destructive_button(btn: &mut Button) {
     btn.set_color(Color::from_rgb(220, 53, 69));
     btn.set_selection_color(Color::from_rgb(187, 45, 59));
     btn.set_label_color(Color::White);
@@ -422,7 +1049,19 @@ fn style_destructive_button(btn: &mut Button) {
     btn.clear_visible_focus();
 }
 
-fn style_choice_widget(choice: &mut Choice) {
+fn style_secondary_button(btn: &mut Button) {
+    btn.set_color(Color::from_rgb(73, 80, 87));
+    btn.set_selection_color(Color::from_rgb(52, 58, 64));
+    btn.set_label_color(Color::White);
+    btn.set_label_font(Font::HelveticaBold);
+    btn.set_label_size(12);
+    btn.set_frame(FrameType::RFlatBox);
+
+    // Simple hover effect using built-in selection color
+    btn.clear_visible_focus();
+}
+
+pub(super) fn style_choice_widget(choice: &mut Choice) {
     choice.set_color(Color::from_rgb(33, 37, 41));
     choice.set_selection_color(Color::from_rgb(13, 110, 253));
     choice.set_label_color(Color::White);
@@ -436,7 +1075,7 @@ fn style_choice_widget(choice: &mut Choice) {
     choice.clear_visible_focus();
 }
 
-fn style_checkbox(checkbox: &mut CheckButton) {
+pub(super) fn style_checkbox(checkbox: &mut CheckButton) {
     checkbox.set_label_color(Color::White);
     checkbox.set_label_font(Font::Helvetica);
     checkbox.set_label_size(12);
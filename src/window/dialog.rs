@@ -43,6 +43,37 @@ pub fn open_multiple_files_dialog(_parent: &Window) -> Option<Vec<PathBuf>> {
     None
 }
 
+pub fn open_file_dialog(title: &str, filter: &str) -> Option<PathBuf> {
+    let mut dialog = FileDialog::new(FileDialogType::BrowseFile);
+    dialog.set_title(title);
+    dialog.set_filter(filter);
+
+    dialog.show();
+
+    let filename = dialog.filename();
+    if !filename.to_string_lossy().is_empty() {
+        return Some(filename);
+    }
+
+    None
+}
+
+pub fn save_file_dialog(title: &str, filter: &str) -> Option<PathBuf> {
+    let mut dialog = FileDialog::new(FileDialogType::BrowseSaveFile);
+    dialog.set_title(title);
+    dialog.set_filter(filter);
+    dialog.set_option(FileDialogOptions::SaveAsConfirm);
+
+    dialog.show();
+
+    let filename = dialog.filename();
+    if !filename.to_string_lossy().is_empty() {
+        return Some(filename);
+    }
+
+    None
+}
+
 pub fn show_error_dialog(_parent: &Window, message: &str) {
     alert_default(message);
 }
@@ -50,3 +81,7 @@ pub fn show_error_dialog(_parent: &Window, message: &str) {
 pub fn show_info_dialog(_parent: &Window, message: &str) {
     message_default(message);
 }
+
+pub fn show_confirm_dialog(_parent: &Window, message: &str, confirm: &str) -> bool {
+    choice2_default(message, "Cancel", confirm, "") == Some(1)
+}
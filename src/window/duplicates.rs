@@ -0,0 +1,136 @@
+use fltk::{app, button::*, enums::*, frame::*, group::*, image::RgbImage, prelude::*, window::*};
+use image::RgbaImage;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::convert::DuplicateGroup;
+use crate::window::app::{style_checkbox, style_destructive_button, style_primary_button};
+
+pub const THUMB_SIZE: u32 = 96;
+const THUMBS_PER_ROW: usize = 6;
+
+// Shows every group with the thumbnails found along with it and returns the
+// files to drop from the batch, or None when cancelled
+pub fn open_duplicates_dialog(groups: &[DuplicateGroup]) -> Option<Vec<PathBuf>> {
+    let mut wind = Window::new(150, 150, 760, 520, None);
+    wind.set_label("Duplicate Images");
+    wind.set_color(Color::from_rgb(26, 26, 26));
+
+    let mut scroll = Scroll::new(10, 10, 740, 440, "");
+    scroll.set_color(Color::from_rgb(35, 40, 47));
+    scroll.set_frame(FrameType::FlatBox);
+    scroll.set_type(ScrollType::Vertical);
+
+    let mut keep_checks = Vec::new();
+    let mut y = 20;
+    for (index, group) in groups.iter().enumerate() {
+        let mut title = Frame::new(20, y, 700, 20, None);
+        title.set_label(&format!(
+            "Group {} ({} images)",
+            index + 1,
+            group.files.len()
+        ));
+        title.set_label_color(Color::White);
+        title.set_label_font(Font::HelveticaBold);
+        title.set_align(Align::Left | Align::Inside);
+        y += 25;
+
+        for (position, path) in group.files.iter().enumerate() {
+            if position > 0 && position % THUMBS_PER_ROW == 0 {
+                y += 135;
+            }
+            let x = 20 + (position % THUMBS_PER_ROW) as i32 * 118;
+
+            let mut thumb = Frame::new(x, y, 110, 105, None);
+            thumb.set_frame(FrameType::FlatBox);
+            thumb.set_color(Color::from_rgb(28, 33, 40));
+            if let Some(image) = group.thumbnails.get(position).and_then(thumbnail) {
+                thumb.set_image(Some(image));
+            }
+
+            let mut keep_check = CheckButton::new(x, y + 108, 110, 20, None);
+            keep_check.set_label(&short_name(path));
+            keep_check.set_tooltip(&path.display().to_string());
+            // The largest image of a group comes first
+            keep_check.set_checked(position == 0);
+            style_checkbox(&mut keep_check);
+
+            keep_checks.push((path.clone(), keep_check));
+        }
+        y += 145;
+    }
+
+    scroll.end();
+
+    let mut hint = Frame::new(
+        10,
+        455,
+        740,
+        20,
+        "Unchecked images are removed from the batch.",
+    );
+    hint.set_label_color(Color::from_rgb(139, 148, 158));
+    hint.set_label_size(12);
+    hint.set_align(Align::Left | Align::Inside);
+
+    let mut cancel_btn = Button::new(510, 480, 100, 30, "Cancel");
+    style_destructive_button(&mut cancel_btn);
+
+    let mut remove_btn = Button::new(620, 480, 130, 30, "Remove Unchecked");
+    style_primary_button(&mut remove_btn);
+
+    wind.end();
+    wind.make_modal(true);
+    wind.show();
+
+    let dropped: Rc<RefCell<Option<Vec<PathBuf>>>> = Rc::new(RefCell::new(None));
+
+    {
+        let mut wind_clone = wind.clone();
+        cancel_btn.set_callback(move |_| {
+            wind_clone.hide();
+        });
+    }
+
+    {
+        let dropped_clone = dropped.clone();
+        let mut wind_clone = wind.clone();
+        remove_btn.set_callback(move |_| {
+            *dropped_clone.borrow_mut() = Some(
+                keep_checks
+                    .iter()
+                    .filter(|(_, check)| !check.is_checked())
+                    .map(|(path, _)| path.clone())
+                    .collect(),
+            );
+            wind_clone.hide();
+        });
+    }
+
+    while wind.shown() {
+        app::wait();
+    }
+
+    dropped.take()
+}
+
+fn thumbnail(img: &RgbaImage) -> Option<RgbImage> {
+    RgbImage::new(
+        img.as_raw(),
+        img.width() as i32,
+        img.height() as i32,
+        ColorDepth::Rgba8,
+    )
+    .ok()
+}
+
+fn short_name(path: &Path) -> String {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    if name.chars().count() > 14 {
+        let start: String = name.chars().take(12).collect();
+        format!("{}…", start)
+    } else {
+        name.into_owned()
+    }
+}
@@ -0,0 +1,94 @@
+use fltk::{app, browser::*, button::*, enums::*, prelude::*, window::*};
+
+use crate::convert::ImageInfo;
+use crate::window::app::style_primary_button;
+
+// One line for the file label, e.g. "JPEG, 4000x3000, Rgb8, 2134.5 KB"
+pub fn info_summary(info: &ImageInfo) -> String {
+    let mut summary = format!(
+        "{}, {}x{}, {:?}, {:.1} KB",
+        info.format_name().to_uppercase(),
+        info.width,
+        info.height,
+        info.color_type,
+        info.file_bytes as f64 / 1024.0
+    );
+    if info.frame_count > 1 {
+        summary.push_str(&format!(", {} frames", info.frame_count));
+    }
+    summary
+}
+
+pub fn open_info_dialog(info: &ImageInfo) {
+    let mut wind = Window::new(200, 200, 520, 420, None);
+    wind.set_label("Image Info");
+    wind.set_color(Color::from_rgb(26, 26, 26));
+
+    let mut browser = Browser::new(10, 10, 500, 360, "");
+    browser.set_color(Color::from_rgb(28, 33, 40));
+    browser.set_selection_color(Color::from_rgb(9, 105, 218));
+    browser.set_frame(FrameType::DownBox);
+    browser.set_text_size(12);
+    browser.set_column_widths(&[150, 340]);
+    browser.set_column_char('\t');
+
+    let file_name = info
+        .path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut rows = vec![
+        ("File".to_string(), file_name),
+        ("Format".to_string(), info.format_name().to_uppercase()),
+        (
+            "Dimensions".to_string(),
+            format!("{} x {}", info.width, info.height),
+        ),
+        ("Color type".to_string(), format!("{:?}", info.color_type)),
+        (
+            "Bit depth".to_string(),
+            format!("{} bits per channel", info.bits_per_channel),
+        ),
+        (
+            "File size".to_string(),
+            format!(
+                "{:.1} KB ({} bytes)",
+                info.file_bytes as f64 / 1024.0,
+                info.file_bytes
+            ),
+        ),
+        ("Frames".to_string(), info.frame_count.to_string()),
+        (
+            "ICC profile".to_string(),
+            info.icc_profile_bytes
+                .map_or("none".to_string(), |bytes| format!("{} bytes", bytes)),
+        ),
+    ];
+    if info.exif.is_empty() {
+        rows.push(("EXIF".to_string(), "none".to_string()));
+    } else {
+        rows.extend(info.exif.iter().cloned());
+    }
+
+    for (name, value) in rows {
+        browser.add(&format!("{}\t{}", name, value));
+    }
+
+    let mut close_btn = Button::new(410, 380, 100, 30, "Close");
+    style_primary_button(&mut close_btn);
+
+    wind.end();
+    wind.make_modal(true);
+    wind.show();
+
+    {
+        let mut wind_clone = wind.clone();
+        close_btn.set_callback(move |_| {
+            wind_clone.hide();
+        });
+    }
+
+    while wind.shown() {
+        app::wait();
+    }
+}
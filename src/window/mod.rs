@@ -1,4 +1,7 @@
 mod app;
 mod dialog;
+mod duplicates;
+mod info;
+mod options;
 
 pub use app::create_app;
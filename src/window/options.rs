@@ -0,0 +1,1233 @@
+use fltk::{
+    app, browser::*, button::*, enums::*, frame::*, group::*, input::*, menu::*, misc::*,
+    prelude::*, valuator::*, window::*,
+};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::convert::{
+    Adjustments, Alignment, Anchor, Canvas, ConvertFormat, ConvertOptions, DecodeLimits, Dither,
+    EncoderSettings, FormatRule, OutputColorType, OutputTarget, QualityThreshold, Resize,
+    ResponsiveSet, RuleCondition, SizeTarget, Transform, UnsharpMask, Watermark, WatermarkSource,
+};
+use crate::window::app::{
+    style_checkbox, style_choice_widget, style_destructive_button, style_primary_button,
+};
+use crate::window::dialog;
+
+// Each tab hands back a closure that copies its widget values into the options
+type OptionsReader = Box<dyn Fn(&mut ConvertOptions)>;
+
+// Batch-only settings that live outside ConvertOptions
+#[derive(Debug, Clone, Default)]
+pub struct BatchExtras {
+    // Encoded alongside the format picked in the main window
+    pub extra_targets: Vec<OutputTarget>,
+    pub responsive: Option<ResponsiveSet>,
+    // Pick the format per file, the main window format is the fallback
+    pub rules: Vec<FormatRule>,
+}
+
+// Passing `batch` adds the Formats, Responsive and Rules tabs
+pub fn open_options_dialog(
+    title: &str,
+    options: &Rc<RefCell<ConvertOptions>>,
+    batch: Option<&Rc<RefCell<BatchExtras>>>,
+) {
+    let mut wind = Window::new(150, 150, 760, 380, None);
+    wind.set_label(title);
+    wind.set_color(Color::from_rgb(26, 26, 26));
+
+    let mut tabs = Tabs::new(10, 10, 740, 310, "");
+    tabs.set_color(Color::from_rgb(35, 40, 47));
+    tabs.set_selection_color(Color::from_rgb(35, 40, 47));
+    tabs.set_label_color(Color::White);
+
+    let current = options.borrow().clone();
+    let mut readers: Vec<OptionsReader> = vec![
+        create_palette_tab(&current),
+        create_color_tab(&current),
+        create_adjust_tab(&current),
+        create_filters_tab(&current),
+        create_watermark_tab(&current),
+        create_canvas_tab(&current),
+        create_output_tab(&current),
+        create_auto_tab(&current),
+        create_verify_tab(&current),
+        create_limits_tab(&current),
+    ];
+    if let Some(batch) = batch {
+        readers.push(create_formats_tab(batch));
+        readers.push(create_responsive_tab(batch));
+        readers.push(create_rules_tab(batch));
+    }
+
+    tabs.end();
+
+    let mut cancel_btn = Button::new(540, 335, 100, 30, "Cancel");
+    style_destructive_button(&mut cancel_btn);
+
+    let mut apply_btn = Button::new(650, 335, 100, 30, "Apply");
+    style_primary_button(&mut apply_btn);
+
+    wind.end();
+    wind.make_modal(true);
+    wind.show();
+
+    {
+        let mut wind_clone = wind.clone();
+        cancel_btn.set_callback(move |_| {
+            wind_clone.hide();
+        });
+    }
+
+    {
+        let options_clone = options.clone();
+        let mut wind_clone = wind.clone();
+        apply_btn.set_callback(move |_| {
+            let mut options = options_clone.borrow_mut();
+            for reader in &readers {
+                reader(&mut options);
+            }
+            wind_clone.hide();
+        });
+    }
+
+    while wind.shown() {
+        app::wait();
+    }
+}
+
+fn create_palette_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Palette");
+
+    add_label(25, 55, "Max colors:");
+    let mut colors_spinner = Spinner::new(180, 55, 80, 30, "");
+    colors_spinner.set_range(2.0, 256.0);
+    colors_spinner.set_step(1.0);
+    colors_spinner.set_value(options.quantize.max_colors as f64);
+    style_spinner(&mut colors_spinner);
+
+    add_label(25, 95, "Dithering:");
+    let mut dither_choice = Choice::new(180, 95, 160, 30, "");
+    dither_choice.add_choice("None");
+    dither_choice.add_choice("Floyd-Steinberg");
+    dither_choice.add_choice("Ordered");
+    dither_choice.set_value(match options.quantize.dither {
+        Dither::None => 0,
+        Dither::FloydSteinberg => 1,
+        Dither::Ordered => 2,
+    });
+    style_choice_widget(&mut dither_choice);
+
+    add_label(25, 135, "Dither strength:");
+    let mut strength_slider = HorValueSlider::new(180, 135, 200, 30, "");
+    strength_slider.set_range(0.0, 1.0);
+    strength_slider.set_step(0.05, 1);
+    strength_slider.set_value(options.quantize.dither_strength as f64);
+    style_slider(&mut strength_slider);
+
+    let mut indexed_check = CheckButton::new(25, 175, 300, 30, "Write indexed PNG");
+    indexed_check.set_checked(options.quantize.indexed_png);
+    style_checkbox(&mut indexed_check);
+
+    add_hint(
+        25,
+        215,
+        "GIF output is always quantized with these settings.",
+    );
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.quantize.max_colors = colors_spinner.value() as u16;
+        options.quantize.dither = match dither_choice.value() {
+            0 => Dither::None,
+            2 => Dither::Ordered,
+            _ => Dither::FloydSteinberg,
+        };
+        options.quantize.dither_strength = strength_slider.value() as f32;
+        options.quantize.indexed_png = indexed_check.is_checked();
+    })
+}
+
+fn create_color_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Color");
+
+    add_label(25, 55, "Output color type:");
+    let mut color_choice = Choice::new(180, 55, 200, 30, "");
+    color_choice.add_choice("Keep source");
+    color_choice.add_choice("RGB 8-bit");
+    color_choice.add_choice("RGBA 8-bit");
+    color_choice.add_choice("Grayscale 8-bit");
+    color_choice.add_choice("Grayscale + alpha 8-bit");
+    color_choice.add_choice("RGB 16-bit");
+    color_choice.add_choice("RGBA 16-bit");
+    color_choice.add_choice("1-bit black and white");
+    color_choice.set_value(match options.color_type {
+        None => 0,
+        Some(OutputColorType::Rgb8) => 1,
+        Some(OutputColorType::Rgba8) => 2,
+        Some(OutputColorType::L8) => 3,
+        Some(OutputColorType::La8) => 4,
+        Some(OutputColorType::Rgb16) => 5,
+        Some(OutputColorType::Rgba16) => 6,
+        Some(OutputColorType::Bit1) => 7,
+    });
+    style_choice_widget(&mut color_choice);
+
+    add_label(25, 95, "Dithering:");
+    let mut dither_choice = Choice::new(180, 95, 160, 30, "");
+    dither_choice.add_choice("None");
+    dither_choice.add_choice("Floyd-Steinberg");
+    dither_choice.add_choice("Ordered");
+    dither_choice.set_value(match options.color_dither {
+        Dither::None => 0,
+        Dither::FloydSteinberg => 1,
+        Dither::Ordered => 2,
+    });
+    style_choice_widget(&mut dither_choice);
+
+    add_hint(
+        25,
+        135,
+        "Dithering applies when reducing 16-bit sources or to 1-bit.",
+    );
+    add_hint(
+        25,
+        160,
+        "16-bit and 1-bit output require PNG; JPEG cannot store alpha.",
+    );
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.color_type = match color_choice.value() {
+            1 => Some(OutputColorType::Rgb8),
+            2 => Some(OutputColorType::Rgba8),
+            3 => Some(OutputColorType::L8),
+            4 => Some(OutputColorType::La8),
+            5 => Some(OutputColorType::Rgb16),
+            6 => Some(OutputColorType::Rgba16),
+            7 => Some(OutputColorType::Bit1),
+            _ => None,
+        };
+        options.color_dither = match dither_choice.value() {
+            0 => Dither::None,
+            2 => Dither::Ordered,
+            _ => Dither::FloydSteinberg,
+        };
+    })
+}
+
+fn create_adjust_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Adjust");
+
+    let current = options
+        .transforms
+        .iter()
+        .find_map(|t| match t {
+            Transform::Adjust(adjustments) => Some(adjustments.clone()),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    add_label(25, 55, "Brightness:");
+    let brightness_slider = add_slider(55, -100.0, 100.0, 1.0, current.brightness);
+
+    add_label(25, 90, "Contrast:");
+    let contrast_slider = add_slider(90, -100.0, 100.0, 1.0, current.contrast);
+
+    add_label(25, 125, "Gamma:");
+    let gamma_slider = add_slider(125, 0.1, 5.0, 0.05, current.gamma);
+
+    add_label(25, 160, "Hue rotation:");
+    let hue_slider = add_slider(160, -180.0, 180.0, 1.0, current.hue_rotation);
+
+    add_label(25, 195, "Saturation:");
+    let saturation_slider = add_slider(195, 0.0, 3.0, 0.05, current.saturation);
+
+    let mut grayscale_check = CheckButton::new(25, 235, 150, 30, "Grayscale");
+    grayscale_check.set_checked(current.grayscale);
+    style_checkbox(&mut grayscale_check);
+
+    let mut invert_check = CheckButton::new(180, 235, 150, 30, "Invert");
+    invert_check.set_checked(current.invert);
+    style_checkbox(&mut invert_check);
+
+    tab.end();
+
+    Box::new(move |options| {
+        let adjustments = Adjustments {
+            brightness: brightness_slider.value() as f32,
+            contrast: contrast_slider.value() as f32,
+            gamma: gamma_slider.value() as f32,
+            hue_rotation: hue_slider.value() as f32,
+            saturation: saturation_slider.value() as f32,
+            grayscale: grayscale_check.is_checked(),
+            invert: invert_check.is_checked(),
+        };
+
+        let adjust = if adjustments.is_identity() {
+            None
+        } else {
+            Some(Transform::Adjust(adjustments))
+        };
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::Adjust(_)),
+            adjust,
+        );
+    })
+}
+
+fn create_filters_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Filters");
+
+    let resize = options.transforms.iter().find_map(|t| match t {
+        Transform::Resize(resize) => Some(resize.clone()),
+        _ => None,
+    });
+    let unsharp = options.transforms.iter().find_map(|t| match t {
+        Transform::UnsharpMask(mask) => Some(mask.clone()),
+        _ => None,
+    });
+    let blur = options.transforms.iter().find_map(|t| match t {
+        Transform::GaussianBlur { sigma } => Some(*sigma),
+        _ => None,
+    });
+
+    // The mask settings are shared by standalone sharpening and sharpen after resize
+    let mask = unsharp
+        .clone()
+        .or_else(|| resize.as_ref().and_then(|r| r.sharpen.clone()))
+        .unwrap_or_default();
+
+    let mut resize_check = CheckButton::new(25, 50, 150, 30, "Resize to fit");
+    resize_check.set_checked(resize.is_some());
+    style_checkbox(&mut resize_check);
+
+    let mut width_spinner = Spinner::new(180, 50, 80, 30, "");
+    width_spinner.set_range(0.0, 65535.0);
+    width_spinner.set_step(1.0);
+    width_spinner.set_value(resize.as_ref().map_or(1920, |r| r.max_width) as f64);
+    style_spinner(&mut width_spinner);
+
+    let mut by_label = Frame::new(265, 50, 20, 30, "x");
+    by_label.set_label_color(Color::White);
+
+    let mut height_spinner = Spinner::new(290, 50, 80, 30, "");
+    height_spinner.set_range(0.0, 65535.0);
+    height_spinner.set_step(1.0);
+    height_spinner.set_value(resize.as_ref().map_or(1920, |r| r.max_height) as f64);
+    style_spinner(&mut height_spinner);
+
+    let mut sharpen_resize_check = CheckButton::new(25, 82, 250, 30, "Sharpen after resize");
+    sharpen_resize_check.set_checked(resize.as_ref().is_some_and(|r| r.sharpen.is_some()));
+    style_checkbox(&mut sharpen_resize_check);
+
+    let mut unsharp_check = CheckButton::new(25, 120, 250, 30, "Unsharp mask");
+    unsharp_check.set_checked(unsharp.is_some());
+    style_checkbox(&mut unsharp_check);
+
+    add_label(45, 152, "Radius:");
+    let radius_slider = add_slider(152, 0.1, 10.0, 0.1, mask.radius);
+
+    add_label(45, 184, "Amount:");
+    let amount_slider = add_slider(184, 0.0, 5.0, 0.05, mask.amount);
+
+    add_label(45, 216, "Threshold:");
+    let threshold_slider = add_slider(216, 0.0, 255.0, 1.0, mask.threshold as f32);
+
+    let mut blur_check = CheckButton::new(25, 254, 150, 30, "Gaussian blur");
+    blur_check.set_checked(blur.is_some());
+    style_checkbox(&mut blur_check);
+    let blur_slider = add_slider(254, 0.1, 20.0, 0.1, blur.unwrap_or(1.0));
+
+    tab.end();
+
+    Box::new(move |options| {
+        let mask = UnsharpMask {
+            radius: radius_slider.value() as f32,
+            amount: amount_slider.value() as f32,
+            threshold: threshold_slider.value() as u8,
+        };
+
+        let resize = resize_check.is_checked().then(|| {
+            Transform::Resize(Resize {
+                max_width: width_spinner.value() as u32,
+                max_height: height_spinner.value() as u32,
+                sharpen: sharpen_resize_check.is_checked().then(|| mask.clone()),
+            })
+        });
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::Resize(_)),
+            resize,
+        );
+
+        let unsharp = unsharp_check
+            .is_checked()
+            .then(|| Transform::UnsharpMask(mask.clone()));
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::UnsharpMask(_)),
+            unsharp,
+        );
+
+        let blur = blur_check.is_checked().then(|| Transform::GaussianBlur {
+            sigma: blur_slider.value() as f32,
+        });
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::GaussianBlur { .. }),
+            blur,
+        );
+    })
+}
+
+fn create_watermark_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Watermark");
+
+    let existing = options.transforms.iter().find_map(|t| match t {
+        Transform::Watermark(watermark) => Some(watermark.clone()),
+        _ => None,
+    });
+    let current = existing.clone().unwrap_or_default();
+
+    add_label(25, 50, "Watermark:");
+    let mut source_choice = Choice::new(180, 50, 160, 30, "");
+    source_choice.add_choice("None");
+    source_choice.add_choice("Logo image");
+    source_choice.add_choice("Text label");
+    source_choice.set_value(match &existing {
+        None => 0,
+        Some(Watermark {
+            source: WatermarkSource::Logo(_),
+            ..
+        }) => 1,
+        Some(_) => 2,
+    });
+    style_choice_widget(&mut source_choice);
+
+    let (logo, text, font, color) = match &current.source {
+        WatermarkSource::Logo(path) => (path.clone(), String::new(), default_font(), [255; 4]),
+        WatermarkSource::Text { text, font, color } => {
+            let font = if font.as_os_str().is_empty() {
+                default_font()
+            } else {
+                font.clone()
+            };
+            (PathBuf::new(), text.clone(), font, *color)
+        }
+    };
+
+    add_label(25, 82, "Logo file:");
+    let logo_input = add_path_input(82, &logo, "Select Logo", "PNG Images\t*.png");
+
+    add_label(25, 114, "Text:");
+    let mut text_input = Input::new(180, 114, 335, 30, "");
+    text_input.set_value(&text);
+    style_input(&mut text_input);
+
+    add_label(25, 146, "Font file:");
+    let font_input = add_path_input(146, &font, "Select Font", "Fonts\t*.{ttf,otf,ttc}");
+
+    add_label(25, 178, "Text color:");
+    let mut color_choice = Choice::new(180, 178, 100, 30, "");
+    color_choice.add_choice("White");
+    color_choice.add_choice("Black");
+    color_choice.set_value(if color[0] < 128 { 1 } else { 0 });
+    style_choice_widget(&mut color_choice);
+
+    let mut anchor_choice = Choice::new(295, 178, 140, 30, "");
+    anchor_choice.add_choice("Top left");
+    anchor_choice.add_choice("Top right");
+    anchor_choice.add_choice("Bottom left");
+    anchor_choice.add_choice("Bottom right");
+    anchor_choice.add_choice("Center");
+    anchor_choice.add_choice("Tiled");
+    anchor_choice.set_value(match current.anchor {
+        Anchor::TopLeft => 0,
+        Anchor::TopRight => 1,
+        Anchor::BottomLeft => 2,
+        Anchor::BottomRight => 3,
+        Anchor::Center => 4,
+        Anchor::Tiled => 5,
+    });
+    style_choice_widget(&mut anchor_choice);
+
+    add_label(25, 210, "Opacity:");
+    let opacity_slider = add_slider(210, 0.0, 1.0, 0.05, current.opacity);
+
+    add_label(25, 242, "Margin (x width):");
+    let margin_slider = add_slider(242, 0.0, 0.2, 0.005, current.margin);
+
+    add_label(25, 274, "Scale (x width):");
+    let scale_slider = add_slider(274, 0.01, 1.0, 0.01, current.scale);
+
+    tab.end();
+
+    Box::new(move |options| {
+        let source = match source_choice.value() {
+            1 => Some(WatermarkSource::Logo(PathBuf::from(logo_input.value()))),
+            2 => Some(WatermarkSource::Text {
+                text: text_input.value(),
+                font: PathBuf::from(font_input.value()),
+                color: if color_choice.value() == 1 {
+                    [0, 0, 0, 255]
+                } else {
+                    [255, 255, 255, 255]
+                },
+            }),
+            _ => None,
+        };
+
+        let watermark = source.map(|source| {
+            Transform::Watermark(Watermark {
+                source,
+                anchor: match anchor_choice.value() {
+                    0 => Anchor::TopLeft,
+                    1 => Anchor::TopRight,
+                    2 => Anchor::BottomLeft,
+                    4 => Anchor::Center,
+                    5 => Anchor::Tiled,
+                    _ => Anchor::BottomRight,
+                },
+                opacity: opacity_slider.value() as f32,
+                margin: margin_slider.value() as f32,
+                scale: scale_slider.value() as f32,
+            })
+        });
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::Watermark(_)),
+            watermark,
+        );
+    })
+}
+
+fn create_canvas_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Canvas");
+
+    let existing = options.transforms.iter().find_map(|t| match t {
+        Transform::FitAndPad(canvas) => Some(canvas.clone()),
+        _ => None,
+    });
+    let current = existing.clone().unwrap_or_default();
+
+    let mut pad_check = CheckButton::new(25, 50, 300, 30, "Fit and pad to a fixed canvas");
+    pad_check.set_checked(existing.is_some());
+    style_checkbox(&mut pad_check);
+
+    add_label(25, 90, "Canvas size:");
+    let mut width_spinner = Spinner::new(180, 90, 80, 30, "");
+    width_spinner.set_range(1.0, 65535.0);
+    width_spinner.set_step(1.0);
+    width_spinner.set_value(current.width as f64);
+    style_spinner(&mut width_spinner);
+
+    let mut by_label = Frame::new(265, 90, 20, 30, "x");
+    by_label.set_label_color(Color::White);
+
+    let mut height_spinner = Spinner::new(290, 90, 80, 30, "");
+    height_spinner.set_range(1.0, 65535.0);
+    height_spinner.set_step(1.0);
+    height_spinner.set_value(current.height as f64);
+    style_spinner(&mut height_spinner);
+
+    add_label(25, 130, "Padding:");
+    let mut background_choice = Choice::new(180, 130, 160, 30, "");
+    background_choice.add_choice("White");
+    background_choice.add_choice("Black");
+    background_choice.add_choice("Transparent");
+    background_choice.set_value(match current.background {
+        [_, _, _, alpha] if alpha < 255 => 2,
+        [0, 0, 0, _] => 1,
+        _ => 0,
+    });
+    style_choice_widget(&mut background_choice);
+
+    add_label(25, 170, "Alignment:");
+    let mut horizontal_choice = Choice::new(180, 170, 100, 30, "");
+    horizontal_choice.add_choice("Left");
+    horizontal_choice.add_choice("Center");
+    horizontal_choice.add_choice("Right");
+    horizontal_choice.set_value(alignment_index(current.horizontal));
+    style_choice_widget(&mut horizontal_choice);
+
+    let mut vertical_choice = Choice::new(290, 170, 100, 30, "");
+    vertical_choice.add_choice("Top");
+    vertical_choice.add_choice("Middle");
+    vertical_choice.add_choice("Bottom");
+    vertical_choice.set_value(alignment_index(current.vertical));
+    style_choice_widget(&mut vertical_choice);
+
+    add_hint(25, 210, "Images are scaled up or down to fit, then padded.");
+
+    tab.end();
+
+    Box::new(move |options| {
+        let canvas = pad_check.is_checked().then(|| {
+            Transform::FitAndPad(Canvas {
+                width: width_spinner.value() as u32,
+                height: height_spinner.value() as u32,
+                background: match background_choice.value() {
+                    1 => [0, 0, 0, 255],
+                    2 => [255, 255, 255, 0],
+                    _ => [255, 255, 255, 255],
+                },
+                horizontal: alignment_from_index(horizontal_choice.value()),
+                vertical: alignment_from_index(vertical_choice.value()),
+            })
+        });
+        replace_transform(
+            &mut options.transforms,
+            |t| matches!(t, Transform::FitAndPad(_)),
+            canvas,
+        );
+    })
+}
+
+fn create_output_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Output");
+
+    let mut quality_check = CheckButton::new(25, 50, 150, 30, "Quality:");
+    quality_check.set_checked(options.encoder.quality.is_some());
+    style_checkbox(&mut quality_check);
+    let quality_slider = add_slider(
+        50,
+        1.0,
+        100.0,
+        1.0,
+        options.encoder.quality.unwrap_or(85) as f32,
+    );
+
+    add_label(25, 85, "AVIF speed:");
+    let speed_slider = add_slider(85, 1.0, 10.0, 1.0, options.encoder.avif_speed as f32);
+
+    add_hint(
+        25,
+        118,
+        "Quality applies to JPEG, WebP and AVIF; WebP is lossless when unset.",
+    );
+
+    let target = options.size_target.clone().unwrap_or_default();
+
+    let mut size_check = CheckButton::new(25, 155, 150, 30, "Max size (KB):");
+    size_check.set_checked(options.size_target.is_some());
+    style_checkbox(&mut size_check);
+
+    let mut size_spinner = Spinner::new(180, 155, 100, 30, "");
+    size_spinner.set_range(1.0, 1_000_000.0);
+    size_spinner.set_step(1.0);
+    size_spinner.set_value((target.max_bytes / 1024) as f64);
+    style_spinner(&mut size_spinner);
+
+    add_label(25, 190, "Lowest quality:");
+    let min_quality_slider = add_slider(190, 1.0, 100.0, 1.0, target.min_quality as f32);
+
+    let mut downscale_check = CheckButton::new(
+        25,
+        225,
+        350,
+        30,
+        "Downscale when quality alone is not enough",
+    );
+    downscale_check.set_checked(target.allow_downscale);
+    style_checkbox(&mut downscale_check);
+
+    add_hint(
+        25,
+        255,
+        "The size limit applies to JPEG, WebP and AVIF outputs only.",
+    );
+
+    let mut fix_extension_check = CheckButton::new(
+        25,
+        280,
+        450,
+        30,
+        "Rename inputs whose extension doesn't match their content",
+    );
+    fix_extension_check.set_checked(options.fix_extension);
+    style_checkbox(&mut fix_extension_check);
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.encoder.quality = quality_check
+            .is_checked()
+            .then(|| quality_slider.value() as u8);
+        options.encoder.avif_speed = speed_slider.value() as u8;
+        options.size_target = size_check.is_checked().then(|| SizeTarget {
+            max_bytes: size_spinner.value() as u64 * 1024,
+            min_quality: min_quality_slider.value() as u8,
+            allow_downscale: downscale_check.is_checked(),
+        });
+        options.fix_extension = fix_extension_check.is_checked();
+    })
+}
+
+fn create_verify_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Verify");
+
+    let threshold = options.verify.clone().unwrap_or_default();
+
+    let mut verify_check =
+        CheckButton::new(25, 50, 350, 30, "Measure quality loss of every output");
+    verify_check.set_checked(options.verify.is_some());
+    style_checkbox(&mut verify_check);
+
+    add_label(25, 90, "Min PSNR (dB):");
+    let psnr_slider = add_slider(90, 10.0, 60.0, 0.5, threshold.min_psnr as f32);
+
+    add_label(25, 130, "Min SSIM:");
+    let ssim_slider = add_slider(130, 0.5, 1.0, 0.005, threshold.min_ssim as f32);
+
+    add_hint(
+        25,
+        170,
+        "Outputs are decoded again and compared with the encoder input.",
+    );
+    add_hint(
+        25,
+        190,
+        "Files below either value are flagged. AVIF outputs cannot be checked.",
+    );
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.verify = verify_check.is_checked().then(|| QualityThreshold {
+            min_psnr: psnr_slider.value(),
+            min_ssim: ssim_slider.value(),
+        });
+    })
+}
+
+fn create_limits_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Limits");
+
+    const MIB: u64 = 1024 * 1024;
+    let limits = &options.limits;
+
+    add_label(25, 50, "Max width (px):");
+    let mut width_spinner = Spinner::new(180, 50, 100, 30, "");
+    width_spinner.set_range(1.0, 1_000_000.0);
+    width_spinner.set_step(1.0);
+    width_spinner.set_value(limits.max_width as f64);
+    style_spinner(&mut width_spinner);
+
+    add_label(25, 90, "Max height (px):");
+    let mut height_spinner = Spinner::new(180, 90, 100, 30, "");
+    height_spinner.set_range(1.0, 1_000_000.0);
+    height_spinner.set_step(1.0);
+    height_spinner.set_value(limits.max_height as f64);
+    style_spinner(&mut height_spinner);
+
+    add_label(25, 130, "Max per image (MB):");
+    let mut alloc_spinner = Spinner::new(180, 130, 100, 30, "");
+    alloc_spinner.set_range(1.0, 1_000_000.0);
+    alloc_spinner.set_step(1.0);
+    alloc_spinner.set_value((limits.max_alloc / MIB) as f64);
+    style_spinner(&mut alloc_spinner);
+
+    add_label(25, 170, "Batch budget (MB):");
+    let mut budget_spinner = Spinner::new(180, 170, 100, 30, "");
+    budget_spinner.set_range(1.0, 1_000_000.0);
+    budget_spinner.set_step(1.0);
+    budget_spinner.set_value((limits.memory_budget / MIB) as f64);
+    style_spinner(&mut budget_spinner);
+
+    add_hint(
+        25,
+        210,
+        "Images over the size or memory limit fail instead of being decoded.",
+    );
+    add_hint(
+        25,
+        230,
+        "Batches only decode as many files at once as fit in the budget.",
+    );
+
+    let mut timeout_check = CheckButton::new(25, 260, 200, 30, "Time limit per file (s):");
+    timeout_check.set_checked(options.timeout.is_some());
+    style_checkbox(&mut timeout_check);
+    let mut timeout_spinner = Spinner::new(230, 260, 100, 30, "");
+    timeout_spinner.set_range(1.0, 86_400.0);
+    timeout_spinner.set_step(1.0);
+    timeout_spinner.set_value(options.timeout.map_or(60, |t| t.as_secs()) as f64);
+    style_spinner(&mut timeout_spinner);
+
+    add_hint(
+        25,
+        300,
+        "Batch files over the limit fail as timed out and keep no output.",
+    );
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.limits = DecodeLimits {
+            max_width: width_spinner.value() as u32,
+            max_height: height_spinner.value() as u32,
+            max_alloc: alloc_spinner.value() as u64 * MIB,
+            memory_budget: budget_spinner.value() as u64 * MIB,
+        };
+        options.timeout = timeout_check
+            .is_checked()
+            .then(|| Duration::from_secs(timeout_spinner.value() as u64));
+    })
+}
+
+fn create_formats_tab(batch: &Rc<RefCell<BatchExtras>>) -> OptionsReader {
+    let tab = create_tab("Formats");
+
+    add_hint(
+        25,
+        45,
+        "Also encode every input into these formats. Quality 0 keeps the default.",
+    );
+
+    let rows = add_format_rows(75, 38, &batch.borrow().extra_targets);
+
+    tab.end();
+
+    let batch = batch.clone();
+    Box::new(move |_| {
+        batch.borrow_mut().extra_targets = read_format_rows(&rows, EncoderSettings::default());
+    })
+}
+
+fn create_auto_tab(options: &ConvertOptions) -> OptionsReader {
+    let tab = create_tab("Auto");
+
+    add_label(25, 45, "Min SSIM:");
+    let ssim_slider = add_slider(45, 0.5, 1.0, 0.005, options.auto.min_ssim as f32);
+
+    add_hint(
+        25,
+        78,
+        "Keeps the smallest candidate above the SSIM floor. AVIF cannot be scored.",
+    );
+
+    let mut rows = add_format_rows(105, 35, &options.auto.candidates);
+    for (format, check, slider) in &mut rows {
+        if !format.can_decode() {
+            check.set_checked(false);
+            check.deactivate();
+            slider.deactivate();
+        }
+    }
+
+    tab.end();
+
+    Box::new(move |options| {
+        options.auto.min_ssim = ssim_slider.value();
+        // Candidates share the AVIF speed from the Output tab
+        let encoder = EncoderSettings {
+            avif_speed: options.encoder.avif_speed,
+            ..EncoderSettings::default()
+        };
+        options.auto.candidates = read_format_rows(&rows, encoder);
+    })
+}
+
+fn create_responsive_tab(batch: &Rc<RefCell<BatchExtras>>) -> OptionsReader {
+    let tab = create_tab("Responsive");
+
+    let current = batch.borrow().responsive.clone();
+    let set = current.clone().unwrap_or_default();
+
+    let mut enable_check = CheckButton::new(25, 50, 350, 30, "Generate responsive image sets");
+    enable_check.set_checked(current.is_some());
+    style_checkbox(&mut enable_check);
+
+    add_label(25, 90, "Widths:");
+    let widths: Vec<String> = set.widths.iter().map(|w| w.to_string()).collect();
+    let mut widths_input = Input::new(180, 90, 250, 30, "");
+    widths_input.set_value(&widths.join(", "));
+    style_input(&mut widths_input);
+
+    add_label(25, 130, "Sizes attribute:");
+    let mut sizes_input = Input::new(180, 130, 250, 30, "");
+    sizes_input.set_value(&set.sizes);
+    style_input(&mut sizes_input);
+
+    add_hint(
+        25,
+        170,
+        "Writes name-WIDTHw.ext per width and format, replacing existing files,",
+    );
+    add_hint(
+        25,
+        190,
+        "plus name.html with a <picture> snippet and name.json describing the set.",
+    );
+    add_hint(25, 210, "The main window format is the <img> fallback.");
+
+    tab.end();
+
+    let batch = batch.clone();
+    Box::new(move |_| {
+        batch.borrow_mut().responsive = enable_check.is_checked().then(|| ResponsiveSet {
+            widths: widths_input
+                .value()
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter_map(|w| w.trim().parse().ok())
+                .collect(),
+            sizes: sizes_input.value().trim().to_string(),
+        });
+    })
+}
+
+fn create_rules_tab(batch: &Rc<RefCell<BatchExtras>>) -> OptionsReader {
+    let tab = create_tab("Rules");
+
+    let rules = Rc::new(RefCell::new(batch.borrow().rules.clone()));
+
+    let mut rule_list = Browser::new(25, 45, 490, 110, "");
+    rule_list.set_type(BrowserType::Hold);
+    rule_list.set_color(Color::from_rgb(28, 33, 40));
+    rule_list.set_selection_color(Color::from_rgb(9, 105, 218));
+    rule_list.set_text_size(12);
+    rule_list.set_frame(FrameType::DownBox);
+    update_rule_list(&mut rule_list, &rules.borrow());
+
+    add_label(25, 165, "If:");
+    let mut condition_choice = Choice::new(70, 165, 170, 30, "");
+    for label in [
+        "Always",
+        "Has transparency",
+        "Photographic",
+        "At most N colors",
+        "Wider than N px",
+        "JPEG source",
+        "PNG source",
+    ] {
+        condition_choice.add_choice(label);
+    }
+    condition_choice.set_value(0);
+    style_choice_widget(&mut condition_choice);
+
+    let mut condition_spinner = Spinner::new(250, 165, 90, 30, "");
+    condition_spinner.set_range(1.0, 100_000.0);
+    condition_spinner.set_step(1.0);
+    condition_spinner.set_value(256.0);
+    style_spinner(&mut condition_spinner);
+
+    // Conditions added with "And" all have to hold, along with the one picked
+    let mut and_btn = Button::new(350, 165, 60, 30, "And");
+    style_primary_button(&mut and_btn);
+    and_btn.set_tooltip("Require this condition too, then pick the next");
+    let pending: Rc<RefCell<Vec<RuleCondition>>> = Rc::new(RefCell::new(Vec::new()));
+    let mut pending_label = Frame::new(420, 165, 320, 30, None);
+    pending_label.set_label_color(Color::from_rgb(139, 148, 158));
+    pending_label.set_label_size(12);
+    pending_label.set_align(Align::Left | Align::Inside);
+
+    add_label(25, 205, "Then:");
+    let mut format_choice = Choice::new(70, 205, 170, 30, "");
+    for format in ConvertFormat::ALL.iter() {
+        format_choice.add_choice(format.name());
+    }
+    format_choice.add_choice("Indexed PNG");
+    format_choice.set_value(0);
+    style_choice_widget(&mut format_choice);
+
+    let mut resize_check = CheckButton::new(250, 205, 110, 30, "Resize to:");
+    style_checkbox(&mut resize_check);
+    let mut resize_spinner = Spinner::new(360, 205, 90, 30, "");
+    resize_spinner.set_range(1.0, 100_000.0);
+    resize_spinner.set_step(1.0);
+    resize_spinner.set_value(4000.0);
+    style_spinner(&mut resize_spinner);
+
+    let mut add_btn = Button::new(25, 245, 100, 30, "Add rule");
+    style_primary_button(&mut add_btn);
+    let mut remove_btn = Button::new(135, 245, 100, 30, "Remove");
+    style_destructive_button(&mut remove_btn);
+
+    add_hint(
+        25,
+        285,
+        "First match wins. Replaces extra formats and responsive sets.",
+    );
+
+    tab.end();
+
+    {
+        let pending = pending.clone();
+        let condition_choice = condition_choice.clone();
+        let condition_spinner = condition_spinner.clone();
+        let mut pending_label = pending_label.clone();
+        and_btn.set_callback(move |_| {
+            let Some(condition) = read_condition(&condition_choice, &condition_spinner) else {
+                return;
+            };
+            let mut pending = pending.borrow_mut();
+            if !pending.contains(&condition) {
+                pending.push(condition);
+            }
+            let described: Vec<String> = pending.iter().map(|c| c.describe()).collect();
+            pending_label.set_label(&format!("{} and…", described.join(" and ")));
+            app::redraw();
+        });
+    }
+
+    {
+        let rules = rules.clone();
+        let mut rule_list = rule_list.clone();
+        add_btn.set_callback(move |_| {
+            let mut conditions = pending.take();
+            if let Some(condition) = read_condition(&condition_choice, &condition_spinner) {
+                if !conditions.contains(&condition) {
+                    conditions.push(condition);
+                }
+            }
+            pending_label.set_label("");
+
+            // The extra entry past the formats is indexed PNG
+            let index = format_choice.value().max(0) as usize;
+            let format = ConvertFormat::ALL
+                .get(index)
+                .cloned()
+                .unwrap_or(ConvertFormat::Png);
+
+            let transforms = if resize_check.is_checked() {
+                vec![Transform::Resize(Resize {
+                    max_width: resize_spinner.value() as u32,
+                    max_height: 0,
+                    sharpen: None,
+                })]
+            } else {
+                Vec::new()
+            };
+
+            rules.borrow_mut().push(FormatRule {
+                conditions,
+                target: OutputTarget::new(format),
+                transforms,
+                indexed_png: index >= ConvertFormat::ALL.len(),
+            });
+            update_rule_list(&mut rule_list, &rules.borrow());
+        });
+    }
+
+    {
+        let rules = rules.clone();
+        let mut rule_list = rule_list.clone();
+        remove_btn.set_callback(move |_| {
+            let selected = rule_list.value();
+            if selected > 0 {
+                rules.borrow_mut().remove(selected as usize - 1);
+                update_rule_list(&mut rule_list, &rules.borrow());
+            }
+        });
+    }
+
+    let batch = batch.clone();
+    Box::new(move |options| {
+        // Rule outputs use the Output tab encoder settings
+        let mut rules = rules.borrow().clone();
+        for rule in &mut rules {
+            rule.target.encoder = options.encoder.clone();
+        }
+        batch.borrow_mut().rules = rules;
+    })
+}
+
+// None for "Always"
+fn read_condition(choice: &Choice, spinner: &Spinner) -> Option<RuleCondition> {
+    let value = spinner.value();
+    match choice.value() {
+        1 => Some(RuleCondition::HasAlpha),
+        2 => Some(RuleCondition::Photographic),
+        3 => Some(RuleCondition::MaxColors(value as usize)),
+        4 => Some(RuleCondition::WiderThan(value as u32)),
+        5 => Some(RuleCondition::SourceFormat(ConvertFormat::Jpeg)),
+        6 => Some(RuleCondition::SourceFormat(ConvertFormat::Png)),
+        _ => None,
+    }
+}
+
+fn update_rule_list(browser: &mut Browser, rules: &[FormatRule]) {
+    browser.clear();
+    for (index, rule) in rules.iter().enumerate() {
+        browser.add(&format!("{}. {}", index + 1, rule.describe()));
+    }
+    app::redraw();
+}
+
+// A checkbox and quality slider per format, quality 0 keeps the encoder default
+fn add_format_rows(
+    y: i32,
+    spacing: i32,
+    current: &[OutputTarget],
+) -> Vec<(ConvertFormat, CheckButton, HorValueSlider)> {
+    let mut rows = Vec::new();
+    for (i, format) in ConvertFormat::ALL.iter().enumerate() {
+        let y = y + i as i32 * spacing;
+        let existing = current.iter().find(|t| t.format == *format);
+
+        let mut format_check = CheckButton::new(25, y, 150, 30, format.name());
+        format_check.set_checked(existing.is_some());
+        style_checkbox(&mut format_check);
+
+        let quality = existing.and_then(|t| t.encoder.quality).unwrap_or(0);
+        let mut quality_slider = add_slider(y, 0.0, 100.0, 1.0, quality as f32);
+        if !format.supports_quality() {
+            quality_slider.deactivate();
+        }
+
+        rows.push((format.clone(), format_check, quality_slider));
+    }
+    rows
+}
+
+fn read_format_rows(
+    rows: &[(ConvertFormat, CheckButton, HorValueSlider)],
+    encoder: EncoderSettings,
+) -> Vec<OutputTarget> {
+    rows.iter()
+        .filter(|(_, check, _)| check.is_checked())
+        .map(|(format, _, slider)| OutputTarget {
+            format: format.clone(),
+            encoder: EncoderSettings {
+                quality: Some(slider.value() as u8).filter(|&q| q > 0),
+                ..encoder.clone()
+            },
+        })
+        .collect()
+}
+
+fn alignment_index(alignment: Alignment) -> i32 {
+    match alignment {
+        Alignment::Start => 0,
+        Alignment::Center => 1,
+        Alignment::End => 2,
+    }
+}
+
+fn alignment_from_index(index: i32) -> Alignment {
+    match index {
+        0 => Alignment::Start,
+        2 => Alignment::End,
+        _ => Alignment::Center,
+    }
+}
+
+// First font found in the usual system locations, so text labels work out of the box
+fn default_font() -> PathBuf {
+    let candidates = [
+        "/usr/share/fonts/truetype/dejavu/DejaVuSans-Bold.ttf",
+        "/usr/share/fonts/TTF/DejaVuSans-Bold.ttf",
+        "/System/Library/Fonts/Helvetica.ttc",
+        "C:\\Windows\\Fonts\\arialbd.ttf",
+    ];
+
+    candidates
+        .iter()
+        .map(Path::new)
+        .find(|path| path.exists())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+// Swap the first matching transform in place so its position relative to the
+// others is kept, append when there was none, or drop it when unset
+fn replace_transform(
+    transforms: &mut Vec<Transform>,
+    matches: impl Fn(&Transform) -> bool,
+    transform: Option<Transform>,
+) {
+    match (transforms.iter().position(matches), transform) {
+        (Some(index), Some(transform)) => transforms[index] = transform,
+        (Some(index), None) => {
+            transforms.remove(index);
+        }
+        (None, Some(transform)) => transforms.push(transform),
+        (None, None) => {}
+    }
+}
+
+fn create_tab(name: &str) -> Group {
+    let mut tab = Group::new(10, 35, 740, 285, None);
+    tab.set_label(name);
+    tab.set_color(Color::from_rgb(35, 40, 47));
+    tab.set_label_color(Color::White);
+    tab
+}
+
+fn add_label(x: i32, y: i32, text: &str) {
+    let mut label = Frame::new(x, y, 150, 30, None);
+    label.set_label(text);
+    label.set_label_color(Color::White);
+    label.set_label_size(12);
+    label.set_align(Align::Left | Align::Inside);
+}
+
+fn add_hint(x: i32, y: i32, text: &str) {
+    let mut hint = Frame::new(x, y, 480, 25, None);
+    hint.set_label(text);
+    hint.set_label_color(Color::from_rgb(139, 148, 158));
+    hint.set_label_size(12);
+    hint.set_align(Align::Left | Align::Inside);
+}
+
+fn add_path_input(y: i32, path: &Path, title: &'static str, filter: &'static str) -> Input {
+    let mut input = Input::new(180, y, 240, 30, "");
+    input.set_value(&path.to_string_lossy());
+    style_input(&mut input);
+
+    let mut browse_btn = Button::new(425, y, 90, 30, "Browse...");
+    style_primary_button(&mut browse_btn);
+
+    let mut input_clone = input.clone();
+    browse_btn.set_callback(move |_| {
+        if let Some(path) = dialog::open_file_dialog(title, filter) {
+            input_clone.set_value(&path.to_string_lossy());
+        }
+    });
+
+    input
+}
+
+fn add_slider(y: i32, min: f64, max: f64, step: f64, value: f32) -> HorValueSlider {
+    let mut slider = HorValueSlider::new(180, y, 250, 30, "");
+    slider.set_range(min, max);
+    slider.set_step(step, 1);
+    slider.set_value(value as f64);
+    style_slider(&mut slider);
+    slider
+}
+
+fn style_input(input: &mut Input) {
+    input.set_color(Color::from_rgb(33, 37, 41));
+    input.set_selection_color(Color::from_rgb(13, 110, 253));
+    input.set_text_color(Color::White);
+    input.set_text_size(12);
+    input.set_cursor_color(Color::White);
+    input.set_frame(FrameType::FlatBox);
+}
+
+fn style_spinner(spinner: &mut Spinner) {
+    spinner.set_color(Color::from_rgb(33, 37, 41));
+    spinner.set_selection_color(Color::from_rgb(13, 110, 253));
+    spinner.set_text_color(Color::White);
+    spinner.set_text_size(12);
+    spinner.set_frame(FrameType::FlatBox);
+    spinner.clear_visible_focus();
+}
+
+fn style_slider(slider: &mut HorValueSlider) {
+    slider.set_color(Color::from_rgb(33, 37, 41));
+    slider.set_selection_color(Color::from_rgb(13, 110, 253));
+    slider.set_text_color(Color::White);
+    slider.set_text_size(12);
+    slider.set_frame(FrameType::FlatBox);
+    slider.clear_visible_focus();
+}